@@ -1,17 +1,20 @@
-use std::collections::HashSet;
-use std::convert::TryInto;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
 use std::io::BufReader;
 use std::path::PathBuf;
 
 use anyhow::{ensure, Result};
 use chrono::{DateTime, FixedOffset, Utc};
-use log::{debug, info, warn};
+use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
 use structopt::{clap, StructOpt};
 
-use crate::utils::{check_csv, process_music, rfc3339, EnvConf};
-use crate::{Music, Platform};
+use crate::config::Config;
+use crate::library::read_manifest;
+use crate::utils::{
+    process_music, retag_music, rfc3339, BuildState, EnvConf, PlatformRegistry, BUILD_STATE_FILE,
+};
+use crate::{Library, Music, Platform};
 
 const EXTENSION: &str = "m4a";
 
@@ -22,8 +25,16 @@ author = clap::crate_authors ! (),
 about = "Build library from csv files"
 )]
 pub struct BuildOpt {
-    #[structopt(short, long, about = "CSV file path", required = true)]
-    csv_file: PathBuf,
+    #[structopt(
+        short,
+        long,
+        about = "CSV file paths, merged into one library",
+        required_unless = "manifest"
+    )]
+    csv_file: Vec<PathBuf>,
+
+    #[structopt(long, about = "File listing CSV files to build, one per line")]
+    manifest: Option<PathBuf>,
 
     #[structopt(short, long, about = "Output directory", required = true)]
     output_dir: PathBuf,
@@ -31,6 +42,12 @@ pub struct BuildOpt {
     #[structopt(short, long, about = "Source directory", required = true)]
     source_dir: PathBuf,
 
+    #[structopt(
+        long,
+        about = "Directory of LOCAL sources [default: the source directory]"
+    )]
+    archive_dir: Option<PathBuf>,
+
     #[structopt(long, about = "Target JSON file", requires = "baseurl")]
     output_json: Option<PathBuf>,
 
@@ -48,6 +65,25 @@ pub struct BuildOpt {
 
     #[structopt(long, about = "Target diff file")]
     output_diff: Option<PathBuf>,
+
+    #[structopt(long, about = "Target JSON file of old to new hashes")]
+    output_redirects: Option<PathBuf>,
+
+    #[structopt(long, about = "Target nginx map of redirects", requires = "baseurl")]
+    output_nginx_map: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        about = "Target _redirects file of redirects",
+        requires = "baseurl"
+    )]
+    output_redirects_file: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        about = "Config file [default: .suimu.toml next to the CSV file]"
+    )]
+    config: Option<PathBuf>,
 }
 
 #[derive(Serialize)]
@@ -58,36 +94,168 @@ struct OutputDiff<'a> {
     last_updated: DateTime<Utc>,
 }
 
+impl<'a> OutputDiff<'a> {
+    /// Entries are compared by `url`, which holds the hash. Fields added
+    /// since the old output was written don't count as changes.
+    fn new(old: &'a [OutputMusic], new: &'a [OutputMusic]) -> Self {
+        let urls =
+            |list: &'a [OutputMusic]| list.iter().map(|x| x.url.as_str()).collect::<HashSet<_>>();
+        let (old_urls, new_urls) = (urls(old), urls(new));
+        Self {
+            added: new
+                .iter()
+                .filter(|x| !old_urls.contains(x.url.as_str()))
+                .collect(),
+            removed: old
+                .iter()
+                .filter(|x| !new_urls.contains(x.url.as_str()))
+                .collect(),
+            last_updated: Utc::now(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, PartialEq)]
 pub struct OutputMusic {
     url: String,
     #[serde(with = "rfc3339::with_rfc3339")]
     datetime: DateTime<FixedOffset>,
     title: String,
+    /// `title` without its variant suffix.
+    #[serde(default)]
+    base_title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    variant: Option<String>,
     artist: String,
     performer: String,
+    #[serde(default)]
+    artists: Vec<String>,
+    #[serde(default)]
+    performers: Vec<String>,
     status: u16,
-    source: String,
+    /// Link to the source video, if public.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    /// Id of the first artist found in the registry.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    artist_id: Option<String>,
+    /// Ids of every artist of `artists` found in the registry.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    artist_ids: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    /// Extra CSV columns selected by `extra_columns` in the config.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    extra: BTreeMap<String, String>,
 }
 
 impl OutputMusic {
-    fn from(mu: &Music, baseurl: &str) -> Self {
-        let info = &crate::utils::PLATFORM_INFO[&mu.video_type];
-        let base_source = info.url_template.replace("{}", &mu.video_id);
+    /// Hash of the file, from the last segment of `url`.
+    fn hash(&self) -> Option<&str> {
+        self.url
+            .rsplit('/')
+            .next()?
+            .strip_suffix(EXTENSION)?
+            .strip_suffix('.')
+    }
+
+    fn from(mu: &Music, baseurl: &str, config: &Config) -> Self {
+        let mut artist_ids = vec![];
+        for x in mu.artists.iter().filter_map(|x| config.artists.get(x)) {
+            if !artist_ids.contains(&x.id) {
+                artist_ids.push(x.id.clone());
+            }
+        }
         Self {
             url: baseurl
                 .replacen("{}", &mu.xxhash, 1)
                 .replacen("{}", EXTENSION, 1),
             datetime: mu.datetime,
             title: mu.title.clone(),
+            base_title: mu.base_title.clone(),
+            variant: mu.variant.clone(),
             artist: mu.artist.clone(),
             performer: mu.performer.clone(),
+            artists: mu.artists.clone(),
+            performers: mu.performers.clone(),
             status: mu.status,
-            source: base_source,
+            source: mu.source_url(&config.platforms),
+            artist_id: artist_ids.first().cloned(),
+            artist_ids,
+            tags: mu.tags.iter().cloned().collect(),
+            extra: mu
+                .extra
+                .iter()
+                .filter(|(k, _)| config.extra_columns.contains(k))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
         }
     }
 }
 
+/// Like [`BuildState::renamed`], for builds made before the state file
+/// existed. The old output has no clip range, so an old entry is only
+/// matched when it is the single unused one of its source and time, and a
+/// single new entry shares them.
+fn renamed_from_output(
+    old: &[OutputMusic],
+    musics: &[Music],
+    platforms: &PlatformRegistry,
+) -> Vec<(usize, String)> {
+    let current = musics
+        .iter()
+        .map(|x| x.xxhash.as_str())
+        .collect::<HashSet<_>>();
+    let built = old
+        .iter()
+        .filter_map(OutputMusic::hash)
+        .collect::<HashSet<_>>();
+    let mut groups = HashMap::<_, (Vec<_>, Vec<_>)>::new();
+    for x in old {
+        if let (Some(source), Some(hash)) = (&x.source, x.hash()) {
+            if !current.contains(hash) {
+                groups
+                    .entry((source.clone(), x.datetime))
+                    .or_default()
+                    .0
+                    .push(hash);
+            }
+        }
+    }
+    for (idx, x) in musics.iter().enumerate() {
+        if built.contains(x.xxhash.as_str()) {
+            continue;
+        }
+        if let Some(group) = x
+            .source_url(platforms)
+            .and_then(|source| groups.get_mut(&(source, x.datetime)))
+        {
+            group.1.push(idx);
+        }
+    }
+    groups
+        .into_values()
+        .filter_map(|(hashes, idxs)| match (&hashes[..], &idxs[..]) {
+            ([hash], [idx]) => Some((*idx, hash.to_string())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Fail on records which couldn't be read, rather than leaving their songs
+/// out of the output.
+fn ensure_well_formed(library: &Library) -> Result<()> {
+    let mut lines = vec![];
+    for x in library.malformed() {
+        let (idx, line) = library.locate(x.line);
+        let path = library.files()[idx].path.display();
+        error!("{}:{}: {}", path, line, x.error);
+        lines.push(format!("{}:{}", path, line));
+    }
+    ensure!(lines.is_empty(), "Malformed records: {}", lines.join(", "));
+    Ok(())
+}
+
 pub struct GlobalStat {
     pub failed_video_items: HashSet<(Platform, String)>,
 }
@@ -105,70 +273,115 @@ impl BuildOpt {
         baseurl: Option<String>,
         output_diff: Option<PathBuf>,
     ) -> Self {
+        // Other options keep their defaults, like when left out on the
+        // command line
         Self {
-            csv_file,
+            csv_file: vec![csv_file],
+            manifest: None,
             output_dir,
             source_dir,
+            archive_dir: None,
+            output_redirects: None,
+            output_nginx_map: None,
+            output_redirects_file: None,
             dry_run,
             ffmpeg,
             ytdl,
             output_json,
             baseurl,
             output_diff,
+            config: None,
         }
     }
 }
 
 pub fn build(opts: BuildOpt) -> Result<()> {
     // --csv-file, readable & parsable
-    let csv_file: PathBuf = opts.csv_file;
-    debug!("CSV file: {:?}", csv_file);
+    let mut csv_files = opts.csv_file;
+    if let Some(manifest) = &opts.manifest {
+        csv_files.extend(read_manifest(manifest)?);
+    }
+    debug!("CSV files: {:?}", csv_files);
     debug!("Output path: {:?}", opts.output_dir);
     debug!("Source path: {:?}", opts.source_dir);
 
-    ensure!(csv_file.exists(), format!("{:?} does not exists", csv_file));
+    ensure!(!csv_files.is_empty(), "No CSV file given");
 
-    let read_file = File::open(csv_file)?;
-    let check_result = check_csv(&read_file)?;
+    let config = Config::discover(opts.config.as_deref(), &csv_files[0])?;
+    let library = Library::open_all(&csv_files, &config)?;
+    ensure_well_formed(&library)?;
 
     info!(
         "CSV successfully validated. {} entries found.",
-        check_result.len()
+        library.rows().len()
     );
 
-    let music_arr: Vec<Music> = check_result
-        .into_iter()
-        .filter_map(|x| {
-            let empty_video_id = x.video_id.is_empty();
-            let x_desc = &x.to_string();
-            match TryInto::<Music>::try_into(x) {
-                Ok(v) => Some(v),
-                Err(e) => {
-                    if !empty_video_id {
-                        warn!("Skipping music {}: {}", x_desc, e.to_string());
-                    }
-                    None
-                }
-            }
-        })
-        .collect();
+    for x in &config.extra_columns {
+        if !library.files().iter().any(|file| file.headers.contains(x)) {
+            warn!("Extra column {:?} not found in the CSV file", x);
+        }
+    }
+
+    for (row, e) in library.errors() {
+        if !row.music.video_id.is_empty() {
+            warn!("Skipping music {}: {}", row.music, e);
+        }
+    }
+    let music_arr = library.musics();
 
     let output_dir: PathBuf = opts.output_dir;
 
     info!("{} valid entries found.", music_arr.len());
 
-    let music_process_arr = music_arr
+    // Entries whose metadata changed since the last build keep their output
+    let state_path = output_dir.join(BUILD_STATE_FILE);
+    let old_state = if state_path.exists() {
+        BuildState::load(&state_path)?
+    } else {
+        BuildState::default()
+    };
+    let renamed = if state_path.exists() {
+        old_state
+            .renamed(music_arr)
+            .into_iter()
+            .map(|(idx, old)| (idx, old.to_string()))
+            .collect()
+    } else {
+        match opts.output_json.as_ref().filter(|x| x.exists()) {
+            Some(path) => {
+                let old = serde_json::from_reader(BufReader::new(File::open(path)?))
+                    .unwrap_or_else(|e| {
+                        warn!("Failed to read old JSON, not renaming entries: {:?}", e);
+                        vec![]
+                    });
+                renamed_from_output(&old, music_arr, &config.platforms)
+            }
+            None => vec![],
+        }
+    };
+    let renamed = renamed
+        .into_iter()
+        .filter(|(idx, old)| {
+            !music_arr[*idx].is_member_only()
+                && output_dir.join(format!("{}.{}", old, EXTENSION)).exists()
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut music_process_arr = music_arr
         .iter()
-        .filter(|x| {
-            if x.is_member_only() {
+        .enumerate()
+        .filter(|(idx, x)| {
+            if x.is_member_only() || renamed.contains_key(idx) {
                 return false;
             }
             let mut dir = output_dir.to_owned();
             dir.push(format!("{}.m4a", x.xxhash));
             !dir.exists()
         })
+        .map(|(_, x)| x)
         .collect::<Vec<_>>();
 
+    info!("{} entries to rename.", renamed.len());
     info!("{} entries to process.", music_process_arr.len());
 
     if opts.dry_run {
@@ -177,21 +390,21 @@ pub fn build(opts: BuildOpt) -> Result<()> {
     }
 
     let env_conf = EnvConf {
+        archive_dir: opts.archive_dir.unwrap_or_else(|| opts.source_dir.clone()),
         source_dir: opts.source_dir,
         output_dir: output_dir.clone(),
         youtube_dl_path: opts.ytdl,
         ffmpeg_path: opts.ffmpeg,
+        platforms: config.platforms.clone(),
     };
 
     let mut global_stat = GlobalStat {
         failed_video_items: HashSet::new(),
     };
 
-    let length = music_process_arr.len();
-
+    // Read before retagging removes the old files of renamed entries
     let mut old_output = None;
-    if opts.output_json.is_some() && opts.output_diff.is_some() {
-        let output_json = opts.output_json.clone().unwrap();
+    if let (Some(output_json), Some(_)) = (&opts.output_json, &opts.output_diff) {
         if !output_json.exists() {
             info!("Old output_json not found, assuming empty.");
         } else {
@@ -207,7 +420,7 @@ pub fn build(opts: BuildOpt) -> Result<()> {
                     .into_iter()
                     .filter(|x| {
                         let mut path = output_dir.clone();
-                        let filename = x.url.split('/').last().unwrap();
+                        let filename = x.url.split('/').next_back().unwrap();
                         path.push(filename);
                         if path.exists() {
                             true
@@ -225,6 +438,16 @@ pub fn build(opts: BuildOpt) -> Result<()> {
         }
     }
 
+    for (idx, old) in &renamed {
+        let x = &music_arr[*idx];
+        if !retag_music(x, old, &env_conf) {
+            warn!("Failed to retag {}, processing it again.", x);
+            music_process_arr.push(x);
+        }
+    }
+
+    let length = music_process_arr.len();
+
     info!("=============== Starting build ===============");
     for (idx, i) in music_process_arr.iter().enumerate() {
         info!("======== Building {} / {} ========", idx + 1, length);
@@ -232,7 +455,35 @@ pub fn build(opts: BuildOpt) -> Result<()> {
     }
     info!("=============== Finishing build ===============");
 
-    if opts.output_json.is_some() {
+    let mut state = BuildState::new(music_arr.iter().filter(|x| {
+        output_dir
+            .join(format!("{}.{}", x.xxhash, EXTENSION))
+            .exists()
+    }));
+    state.redirects = old_state.redirects.clone();
+    // Failed retags which failed processing too have nothing to redirect to
+    for (idx, old) in &renamed {
+        let new = &music_arr[*idx].xxhash;
+        if output_dir.join(format!("{}.{}", new, EXTENSION)).exists() {
+            state.redirects.add(old, new);
+        }
+    }
+    state.save(&state_path)?;
+
+    if let Some(path) = &opts.output_redirects {
+        std::fs::write(path, serde_json::to_string(&state.redirects)?)?;
+    }
+    if let Some(baseurl) = &opts.baseurl {
+        let url = |hash: &str| baseurl.replacen("{}", hash, 1).replacen("{}", EXTENSION, 1);
+        if let Some(path) = &opts.output_nginx_map {
+            std::fs::write(path, state.redirects.to_nginx_map(url))?;
+        }
+        if let Some(path) = &opts.output_redirects_file {
+            std::fs::write(path, state.redirects.to_redirects_file(url))?;
+        }
+    }
+
+    if let Some(output_json) = opts.output_json {
         // Generate new output.
         let baseurl = opts.baseurl.unwrap();
         let new_output = music_arr
@@ -251,23 +502,146 @@ pub fn build(opts: BuildOpt) -> Result<()> {
                     true
                 }
             })
-            .map(|x| OutputMusic::from(x, &baseurl))
+            .map(|x| OutputMusic::from(x, &baseurl, &config))
             .collect::<Vec<_>>();
         let output_json_text = serde_json::to_string(&new_output)?;
-        std::fs::write(opts.output_json.unwrap(), output_json_text)?;
+        std::fs::write(output_json, output_json_text)?;
 
         // Writing diff.
         if let Some(oop) = old_output {
-            let removed = oop.iter().filter(|x| !new_output.contains(x)).collect();
-            let added = new_output.iter().filter(|x| !oop.contains(x)).collect();
-            let output_json_text = serde_json::to_string(&OutputDiff {
-                removed,
-                added,
-                last_updated: Utc::now(),
-            })?;
+            let output_json_text = serde_json::to_string(&OutputDiff::new(&oop, &new_output))?;
             std::fs::write(opts.output_diff.unwrap(), output_json_text)?;
         }
     }
 
     Ok(())
 }
+
+#[test]
+fn test_output_artist_ids() {
+    let artists = crate::utils::ArtistRegistry::from_toml(
+        r#"
+[[artist]]
+id = "hoshimachi-suisei"
+name = "星街すいせい"
+aliases = ["Suisei"]
+
+[[artist]]
+id = "sakura-miko"
+name = "さくらみこ"
+"#,
+    )
+    .unwrap();
+    let config = Config {
+        artists,
+        ..Config::default()
+    };
+    let music = Music::try_from(crate::MaybeMusic {
+        datetime: "2021-06-25T22:30+09:00".to_string(),
+        video_type: "YOUTUBE".to_string(),
+        video_id: "ZfDYRy17CBY".to_string(),
+        status: Some(0),
+        title: "Title".to_string(),
+        artist: "Unknown × さくらみこ × Suisei × 星街すいせい".to_string(),
+        ..Default::default()
+    })
+    .unwrap();
+    let output = OutputMusic::from(&music, "https://example.com/{}.{}", &config);
+    assert_eq!(output.artist_ids, ["sakura-miko", "hoshimachi-suisei"]);
+    assert_eq!(output.artist_id.as_deref(), Some("sakura-miko"));
+}
+
+#[test]
+fn test_renamed_from_output() {
+    let config = Config::default();
+    let music = |video_id: &str, title: &str| {
+        Music::try_from(crate::MaybeMusic {
+            datetime: "2021-06-25T22:30+09:00".to_string(),
+            video_type: "YOUTUBE".to_string(),
+            video_id: video_id.to_string(),
+            status: Some(0),
+            title: title.to_string(),
+            ..Default::default()
+        })
+        .unwrap()
+    };
+    let output = |musics: &[Music]| {
+        musics
+            .iter()
+            .map(|x| OutputMusic::from(x, "https://example.com/{}.{}", &config))
+            .collect::<Vec<_>>()
+    };
+    let old = [
+        music("ZfDYRy17CBY", "Bluerose"),
+        music("XXXXXXXXXXX", "Stellar Stellar"),
+        music("XXXXXXXXXXX", "NEXT COLOR PLANET"),
+    ];
+    let musics = [
+        music("ZfDYRy17CBY", "Bluerose (Acoustic ver.)"),
+        music("XXXXXXXXXXX", "Stellar Stellar (Live)"),
+        music("XXXXXXXXXXX", "NEXT COLOR PLANET (Live)"),
+    ];
+    assert_eq!(
+        OutputMusic::from(&old[0], "https://example.com/{}.{}", &config).hash(),
+        Some(old[0].xxhash.as_str())
+    );
+    // Clips of the second video can't be told apart
+    assert_eq!(
+        renamed_from_output(&output(&old), &musics, &config.platforms),
+        [(0, old[0].xxhash.clone())]
+    );
+    assert!(renamed_from_output(&output(&musics), &musics, &config.platforms).is_empty());
+}
+
+#[test]
+fn test_ensure_well_formed() {
+    let config = Config::default();
+    let open = |path: &str, source: &str| {
+        Library::from_reader(source.as_bytes(), &config)
+            .unwrap()
+            .with_path(std::path::Path::new(path))
+    };
+    let header =
+        "datetime,video_type,video_id,clip_start,clip_end,status,title,artist,performer,comment\n";
+    let row =
+        "2021-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBY,,,0,Bluerose,星街すいせい,星街すいせい,\n";
+    let mut library = open("a.csv", &format!("{}{}", header, row));
+    assert!(ensure_well_formed(&library).is_ok());
+    library.merge(open(
+        "b.csv",
+        &format!("{}{}2021-06-25T22:30+09:00,YOUTUBE\n", header, row),
+    ));
+    assert_eq!(
+        ensure_well_formed(&library).unwrap_err().to_string(),
+        "Malformed records: b.csv:3"
+    );
+}
+
+#[test]
+fn test_output_diff() {
+    let config = Config::default();
+    let music = |title: &str| {
+        let music = Music::try_from(crate::MaybeMusic {
+            datetime: "2021-06-25T22:30+09:00".to_string(),
+            video_type: "YOUTUBE".to_string(),
+            video_id: "ZfDYRy17CBY".to_string(),
+            status: Some(0),
+            title: title.to_string(),
+            artist: "星街すいせい".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        OutputMusic::from(&music, "https://example.com/{}.{}", &config)
+    };
+    let new = [music("Bluerose"), music("Stellar Stellar")];
+    // Written before `artists` and `base_title` existed
+    let mut json = serde_json::to_value(&new[..1]).unwrap();
+    for key in ["artists", "performers", "base_title"] {
+        json[0].as_object_mut().unwrap().remove(key);
+    }
+    let old = serde_json::from_value::<Vec<OutputMusic>>(json).unwrap();
+    let diff = OutputDiff::new(&old, &new);
+    assert!(diff.removed.is_empty());
+    assert_eq!(diff.added.len(), 1);
+    assert_eq!(diff.added[0].title, "Stellar Stellar");
+}
@@ -1,17 +1,15 @@
-use std::fs::File;
 use std::path::PathBuf;
-use std::str::FromStr;
 
 use anyhow::{anyhow, ensure, Result};
-use lazy_static::lazy_static;
-use levenshtein::levenshtein;
-use log::{error, info, warn};
-use regex::Regex;
+use log::{error, info};
 use structopt::{clap, StructOpt};
-use unicode_normalization::{is_nfc, UnicodeNormalization};
 
-use crate::utils::{check_csv, check_logic};
-use crate::{MaybeMusic, Music, Platform};
+use crate::config::Config;
+use crate::library::read_manifest;
+use crate::lint::{self, OutputFormat};
+use crate::utils::Rows;
+
+const DEFAULT_CSV_FILE: &str = "suisei-music.csv";
 
 #[derive(StructOpt)]
 #[structopt(
@@ -21,184 +19,131 @@ about = "Validate csv files"
 )]
 pub struct CheckOpt {
     #[structopt(
-        about = "The CSV file to check",
-        default_value = "suisei-music.csv",
-        index = 1,
-        required = true
+        about = "The CSV files to check as one library [default: suisei-music.csv]",
+        index = 1
+    )]
+    csv_files: Vec<PathBuf>,
+
+    #[structopt(long, about = "File listing CSV files to check, one per line")]
+    manifest: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        about = "Config file [default: .suimu.toml next to the CSV file]"
     )]
-    csv_file: PathBuf,
+    config: Option<PathBuf>,
 
     #[structopt(short, long, about = "Only check formats")]
     format_only: bool,
 
-    #[structopt(long)]
+    #[structopt(long, conflicts_with = "format")]
     json_output: bool,
-}
 
-/// Return the Levenshtein ratio of two strings. SHall be a value between 0 and
-/// 1.
-fn similarity_ratio(a: &str, b: &str) -> f32 {
-    let len = a.chars().count().max(b.chars().count());
-    1f32 - (levenshtein(a, b) as f32) / (len as f32)
-}
-
-fn similarity_check(
-    field_name: &str,
-    musics: &[MaybeMusic],
-    picker: impl for<'a> Fn(&'a MaybeMusic) -> &'a str,
-) {
-    for (i, one) in musics.iter().map(&picker).enumerate() {
-        for two in musics.iter().map(&picker).skip(i + 1) {
-            if one == two {
-                continue;
-            }
-            let sim = similarity_ratio(one, two);
-            if sim > 0.75 {
-                warn!(
-                    "[{}] {} & {}: Similar titles ({})",
-                    field_name, one, two, sim
-                );
-            }
-        }
-    }
-}
-
-lazy_static! {
-    static ref RE: Regex = Regex::new(r" ?[（\(].+[）\)]$").unwrap();
+    #[structopt(
+        long,
+        about = "Output format of diagnostics",
+        default_value = "human",
+        possible_values = &["human", "json", "sarif", "github"]
+    )]
+    format: OutputFormat,
 }
 
 pub fn check(opts: CheckOpt) -> Result<()> {
-    let mut has_err = false;
-
-    let csv_file: PathBuf = opts.csv_file;
-    info!("CSV file: {:?}", csv_file);
-
-    ensure!(csv_file.exists(), format!("{:?} does not exists", csv_file));
-
-    let read_file = File::open(csv_file).unwrap();
-    let check_result =
-        check_csv(&read_file).map_err(|e| anyhow!(format!("CSV validation failed: {}", e)))?;
-
-    info!(
-        "CSV successfully validated. {} entries found.",
-        check_result.len()
-    );
-
-    if opts.format_only {
-        return Ok(());
+    let mut csv_files = opts.csv_files;
+    if let Some(manifest) = &opts.manifest {
+        csv_files.extend(read_manifest(manifest)?);
     }
-
-    // Support analysis
-    info!("Checking entry support...");
-    for x in &check_result {
-        if x.video_type.is_empty() {
-            // Often used to skip a conversion
-            warn!("{}: Empty video_type", x);
-            continue;
-        }
-        if let Err(v) = Platform::from_str(&x.video_type) {
-            error!("{}: {}", x, v);
-            has_err = true;
-        }
+    if csv_files.is_empty() {
+        csv_files.push(PathBuf::from(DEFAULT_CSV_FILE));
     }
+    info!("CSV files: {:?}", csv_files);
 
-    // Potential typo analysis
-    info!("Checking potential typos...");
-    for x in &check_result {
-        if x.title.trim() != x.title {
-            error!("{}: Spaces around title", x);
-            has_err = true;
-        }
-        if x.artist.trim() != x.artist {
-            error!("{}: Spaces around artist", x);
-            has_err = true;
-        }
+    let mut sources = vec![];
+    for csv_file in &csv_files {
+        ensure!(csv_file.exists(), format!("{:?} does not exists", csv_file));
+        sources.push(std::fs::read_to_string(csv_file)?);
     }
 
-    // Unicode NFC check
-    info!("Checking Unicode NFC conformity...");
-    for x in &check_result {
-        if !is_nfc(&x.title) {
-            error!(
-                "{}: Title is not in NFC, please change to '{}'",
-                x,
-                x.title.chars().nfc()
-            );
-            has_err = true;
-        }
-        if !is_nfc(&x.artist) {
-            error!(
-                "{}: Artist is not in NFC, please change to '{}'",
-                x,
-                x.artist.chars().nfc()
-            );
-            has_err = true;
+    if opts.format_only {
+        let mut entries = 0;
+        let mut malformed = 0;
+        for (csv_file, source) in csv_files.iter().zip(&sources) {
+            for x in Rows::new(source.as_bytes())? {
+                match x {
+                    Ok(_) => entries += 1,
+                    Err(e) => {
+                        error!("{}: {}", csv_file.display(), e);
+                        malformed += 1;
+                    }
+                }
+            }
         }
+        ensure!(
+            malformed == 0,
+            format!("CSV validation failed: {} malformed rows", malformed)
+        );
+        info!("CSV successfully validated. {} entries found.", entries);
+        return Ok(());
     }
 
-    info!("Check similar metadatas...");
-
-    // Title: ignore bracketed suffix
-    let mut check_result_altered = check_result.clone();
-    for i in check_result_altered.iter_mut() {
-        i.title = RE.replace_all(&i.title, "").to_string();
-    }
-
-    similarity_check("Title", &check_result_altered, |x| &x.title);
-    similarity_check("Artist", &check_result, |x| &x.artist);
-
-    info!("Validating fields...");
-
-    let converted_result = check_result
-        .into_iter()
-        .filter_map(|x| {
-            let x_desc = x.to_string();
-            let v: Result<Music> = x.try_into();
-            match v {
-                Ok(m) => Some(m),
-                Err(e) => {
-                    if &e.to_string() == "No status present" {
-                        // Often used to skip a conversion
-                        warn!("{}: Failed to convert to music: {}", x_desc, e);
-                    } else {
-                        error!("{}: Failed to convert to music: {}", x_desc, e);
-                        has_err = true;
+    let config = Config::discover(opts.config.as_deref(), &csv_files[0])?;
+    let files = csv_files
+        .iter()
+        .map(PathBuf::as_path)
+        .zip(sources.iter().map(String::as_str))
+        .collect::<Vec<_>>();
+    let report = lint::check_files(&files, &config)
+        .map_err(|e| anyhow!(format!("CSV validation failed: {}", e)))?;
+
+    info!("{} entries found.", report.library.rows().len());
+
+    let paths = csv_files
+        .iter()
+        .map(|x| x.display().to_string())
+        .collect::<Vec<_>>();
+    let path = &paths[0];
+    match opts.format {
+        OutputFormat::Human => {
+            for diag in &report.diagnostics {
+                let idx = match &diag.file {
+                    Some(file) => paths.iter().position(|x| x == file),
+                    None => Some(0),
+                };
+                let rendered = match idx {
+                    Some(idx) => {
+                        let headers = &report.library.files()[idx].headers;
+                        lint::render(diag, path, &sources[idx], headers)
                     }
-                    None
-                }
+                    // Outside of the CSV files, e.g. in the suppression file
+                    None => {
+                        let file = diag.file.as_deref().unwrap_or_default();
+                        let source = std::fs::read_to_string(file).unwrap_or_default();
+                        lint::render(diag, path, &source, &[])
+                    }
+                };
+                eprintln!("{}", rendered);
             }
-        })
-        .collect::<Vec<Music>>();
-
-    // Logic analysis
-    info!("Checking entry logic...");
-    for x in &converted_result {
-        if let Err(v) = check_logic(x) {
-            error!("{}: {}", x, v);
-            has_err = true;
         }
+        OutputFormat::Json => println!("{}", lint::to_json(&report.diagnostics, path)),
+        OutputFormat::Sarif => println!("{}", lint::to_sarif(&report.diagnostics, path)),
+        OutputFormat::Github => print!("{}", lint::to_github(&report.diagnostics, path)),
     }
 
-    info!("Check finished.");
+    let errors = report.diagnostics.iter().filter(|x| x.is_error()).count();
+    info!(
+        "Check finished. {} errors, {} warnings.",
+        errors,
+        report.diagnostics.len() - errors
+    );
 
     if opts.json_output {
-        let base = serde_json::to_string(&converted_result).unwrap();
+        let base = serde_json::to_string(report.library.musics()).unwrap();
         println!("{}", base);
     }
 
-    if has_err {
+    if report.has_errors() {
         Err(anyhow!("Some hard checks didn't pass."))
     } else {
         Ok(())
     }
 }
-
-#[test]
-fn test_similarity_ratio() {
-    // Normal cases
-    assert_eq!(similarity_ratio("test", "test"), 1.0);
-    assert_eq!(similarity_ratio("abcd", "efgh"), 0.0);
-    // CJK
-    assert_eq!(similarity_ratio("双海亚美", "双海真美"), 0.75);
-    assert_eq!(similarity_ratio("中文Aka", "英文Aka"), 0.8);
-}
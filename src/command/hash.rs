@@ -0,0 +1,95 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, ensure, Result};
+use log::warn;
+use regex::Regex;
+use structopt::{clap, StructOpt};
+
+use crate::utils::{Row, Rows};
+
+#[derive(StructOpt)]
+#[structopt(
+version = clap::crate_version ! (),
+author = clap::crate_authors ! (),
+about = "Print the hash of rows, which names their output file"
+)]
+pub struct HashOpt {
+    #[structopt(about = "The CSV file, or - for stdin", default_value = "-", index = 1)]
+    csv_file: PathBuf,
+
+    #[structopt(short, long, about = "Line numbers of rows to print")]
+    line: Vec<u64>,
+
+    #[structopt(
+        short,
+        long,
+        about = "Pattern matched against title, artist, performer and video_id"
+    )]
+    filter: Option<String>,
+}
+
+pub fn hash(opts: HashOpt) -> Result<()> {
+    let source: Box<dyn Read> = if opts.csv_file.as_os_str() == "-" {
+        Box::new(std::io::stdin())
+    } else {
+        ensure!(
+            opts.csv_file.exists(),
+            format!("{:?} does not exists", opts.csv_file)
+        );
+        Box::new(File::open(&opts.csv_file)?)
+    };
+    let filter = opts
+        .filter
+        .as_deref()
+        .map(Regex::new)
+        .transpose()
+        .map_err(|e| anyhow!("Invalid filter: {}", e))?;
+
+    for row in Rows::new(source)? {
+        match row {
+            Ok(row) if selected(&row, &opts.line, filter.as_ref()) => {
+                println!("{}\t{}\t{}", row.line, row.music.hash(), row.music)
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Skipping malformed row: {}", e),
+        }
+    }
+    Ok(())
+}
+
+/// Whether `row` is on one of `lines` and matches `filter`. No lines selects
+/// every row.
+fn selected(row: &Row, lines: &[u64], filter: Option<&Regex>) -> bool {
+    let x = &row.music;
+    (lines.is_empty() || lines.contains(&row.line))
+        && filter.is_none_or(|re| {
+            [&x.title, &x.artist, &x.performer, &x.video_id]
+                .iter()
+                .any(|value| re.is_match(value))
+        })
+}
+
+#[test]
+fn test_selected() {
+    let rows = crate::utils::check_csv_rows(
+        "datetime,video_type,video_id,clip_start,clip_end,status,title,artist,performer,comment
+2021-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBY,,,0,Bluerose,星街すいせい,星街すいせい,
+2018-03-27T20:54+09:00,TWITTER,978601113791299585,,,0,Starduster,ジミーサムP,星街すいせい,
+"
+        .as_bytes(),
+    )
+    .unwrap();
+    let select = |lines: &[u64], filter: Option<&Regex>| {
+        rows.iter()
+            .filter(|x| selected(x, lines, filter))
+            .map(|x| x.line)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(select(&[], None), [2, 3]);
+    assert_eq!(select(&[3], None), [3]);
+    let re = Regex::new("ジミー").unwrap();
+    assert_eq!(select(&[], Some(&re)), [3]);
+    assert!(select(&[2], Some(&re)).is_empty());
+}
@@ -3,9 +3,13 @@ pub mod build_interactive;
 pub mod check;
 #[cfg(feature = "update")]
 pub mod check_update;
+pub mod hash;
+pub mod suppress;
 
 pub use build::*;
 pub use build_interactive::*;
 pub use check::*;
 #[cfg(feature = "update")]
 pub use check_update::*;
+pub use hash::*;
+pub use suppress::*;
@@ -0,0 +1,83 @@
+use std::fs::File;
+use std::path::PathBuf;
+
+use anyhow::{ensure, Result};
+use log::info;
+use requestty::Question;
+use structopt::{clap, StructOpt};
+
+use crate::config::Config;
+use crate::lint::{cluster_diagnostic, similar_clusters, similarity_values};
+use crate::utils::check_csv_rows;
+
+#[derive(StructOpt)]
+#[structopt(
+version = clap::crate_version ! (),
+author = clap::crate_authors ! (),
+about = "Accept similarity warnings into the suppression file"
+)]
+pub struct SuppressOpt {
+    #[structopt(
+        about = "The CSV file to check",
+        default_value = "suisei-music.csv",
+        index = 1
+    )]
+    csv_file: PathBuf,
+
+    #[structopt(
+        long,
+        about = "Config file [default: .suimu.toml next to the CSV file]"
+    )]
+    config: Option<PathBuf>,
+
+    #[structopt(short, long, about = "Accept all warnings without asking")]
+    all: bool,
+
+    #[structopt(long, about = "Remove stale suppressions")]
+    prune: bool,
+}
+
+pub fn suppress(opts: SuppressOpt) -> Result<()> {
+    let csv_file: PathBuf = opts.csv_file;
+    ensure!(csv_file.exists(), format!("{:?} does not exists", csv_file));
+
+    let rows = check_csv_rows(File::open(&csv_file)?)?;
+    let config = Config::discover(opts.config.as_deref(), &csv_file)?;
+    let path = config.suppression_path();
+    let mut suppressions = config.suppressions.clone();
+
+    if opts.prune {
+        let removed = suppressions.prune(&similarity_values(&rows, &config));
+        info!("Removed {} stale suppressions.", removed);
+    }
+
+    let clusters = similar_clusters(&rows, &config);
+    info!("{} unsuppressed similarity warnings found.", clusters.len());
+
+    let mut added = 0;
+    for (field, cluster) in &clusters {
+        let accept = opts.all || {
+            let message = cluster_diagnostic(field, cluster).message;
+            requestty::prompt_one(
+                Question::confirm("accept")
+                    .message(format!("Accept {}?", message))
+                    .default(false)
+                    .build(),
+            )?
+            .as_bool()
+            .unwrap_or(false)
+        };
+        if accept {
+            suppressions.add(field, cluster);
+            added += 1;
+        }
+    }
+
+    if suppressions != config.suppressions {
+        suppressions.save(&path)?;
+        info!("Updated {:?}: {} suppressions added.", path, added);
+    } else {
+        info!("Nothing changed.");
+    }
+    Ok(())
+}
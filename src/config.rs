@@ -0,0 +1,299 @@
+//! `.suimu.toml`, the configuration file checked in next to the dataset.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use log::{debug, warn};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::lint::{Severity, Suppressions, RULES, SUPPRESSION_FILE};
+use crate::utils::{
+    ArtistRegistry, CreditParser, PlatformOverride, PlatformRegistry, VariantParser, ARTISTS_FILE,
+};
+
+pub const CONFIG_FILE: &str = ".suimu.toml";
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub check: CheckConfig,
+    /// How `artist` and `performer` are split into names.
+    pub credits: CreditParser,
+    /// Variant labels parsed from title suffixes.
+    pub variants: VariantParser,
+    /// Registry of canonical artist names and aliases.
+    pub artists_file: PathBuf,
+    /// Extra CSV columns included in the output JSON of `build`.
+    pub extra_columns: Vec<String>,
+    /// Platforms added or overriding the built-in ones, by name.
+    #[serde(rename = "platforms")]
+    pub platform_overrides: HashMap<String, PlatformOverride>,
+    /// Directory relative paths in the config are resolved against.
+    #[serde(skip)]
+    pub base_dir: PathBuf,
+    #[serde(skip)]
+    pub suppressions: Suppressions,
+    #[serde(skip)]
+    pub artists: ArtistRegistry,
+    #[serde(skip)]
+    pub platforms: PlatformRegistry,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            check: CheckConfig::default(),
+            credits: CreditParser::default(),
+            variants: VariantParser::default(),
+            artists_file: PathBuf::from(ARTISTS_FILE),
+            extra_columns: vec![],
+            platform_overrides: HashMap::new(),
+            base_dir: PathBuf::new(),
+            suppressions: Suppressions::default(),
+            artists: ArtistRegistry::default(),
+            platforms: PlatformRegistry::default(),
+        }
+    }
+}
+
+/// Level of a lint rule, overriding its default severity.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleLevel {
+    Off,
+    Warning,
+    Error,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CheckConfig {
+    /// Rule code to level, e.g. `similar-values = "off"`.
+    pub rules: HashMap<String, RuleLevel>,
+    /// Values with a Levenshtein ratio above this are reported as similar.
+    pub similarity_threshold: f32,
+    /// Gaps between clips of the same video shorter than this (in seconds)
+    /// are reported.
+    pub suspicious_gap: f32,
+    /// Extra patterns removed from titles before comparing them.
+    pub title_patterns: Vec<String>,
+    /// Accepted similarity warnings.
+    pub suppression_file: PathBuf,
+    /// Offset every datetime is expected to have.
+    pub utc_offset: String,
+    /// Datetimes before this date (`YYYY-MM-DD`) are reported.
+    pub earliest_date: Option<String>,
+    /// Tweets whose datetime differs from the time encoded in their ID by
+    /// more than this (in seconds) are reported.
+    pub snowflake_window: i64,
+    /// Tags allowed in the `tags` column. Any tag is allowed if empty.
+    pub allowed_tags: Vec<String>,
+    #[serde(skip)]
+    title_regexes: Vec<Regex>,
+    #[serde(skip)]
+    offset: FixedOffset,
+    #[serde(skip)]
+    earliest: Option<NaiveDate>,
+}
+
+impl Default for CheckConfig {
+    fn default() -> Self {
+        Self {
+            rules: HashMap::new(),
+            similarity_threshold: 0.75,
+            suspicious_gap: crate::utils::SUSPICIOUS_GAP,
+            title_patterns: vec![],
+            suppression_file: PathBuf::from(SUPPRESSION_FILE),
+            utc_offset: "+09:00".to_string(),
+            earliest_date: None,
+            snowflake_window: 3600,
+            allowed_tags: vec![],
+            title_regexes: vec![],
+            offset: FixedOffset::east(9 * 3600),
+            earliest: None,
+        }
+    }
+}
+
+impl CheckConfig {
+    fn prepare(&mut self) -> Result<()> {
+        for rule in self.rules.keys() {
+            if !RULES.contains(&rule.as_str()) {
+                warn!("Unknown rule in config: {}", rule);
+            }
+        }
+        self.title_regexes = self
+            .title_patterns
+            .iter()
+            .map(|x| Regex::new(x).map_err(|e| anyhow!("Invalid title pattern {:?}: {}", x, e)))
+            .collect::<Result<_>>()?;
+        self.offset = DateTime::parse_from_str(
+            &format!("2000-01-01T00:00{}", self.utc_offset),
+            "%Y-%m-%dT%H:%M%z",
+        )
+        .map(|x| *x.offset())
+        .map_err(|_| anyhow!("Invalid UTC offset {:?}", self.utc_offset))?;
+        self.earliest = self
+            .earliest_date
+            .as_ref()
+            .map(|x| {
+                NaiveDate::parse_from_str(x, "%Y-%m-%d")
+                    .map_err(|_| anyhow!("Invalid earliest date {:?}", x))
+            })
+            .transpose()?;
+        Ok(())
+    }
+
+    pub fn title_regexes(&self) -> &[Regex] {
+        &self.title_regexes
+    }
+
+    pub fn offset(&self) -> FixedOffset {
+        self.offset
+    }
+
+    pub fn earliest_date(&self) -> Option<NaiveDate> {
+        self.earliest
+    }
+
+    pub fn is_enabled(&self, rule: &str) -> bool {
+        self.rules.get(rule) != Some(&RuleLevel::Off)
+    }
+
+    /// Severity of a rule, or `None` if it's turned off.
+    pub fn severity(&self, rule: &str, default: Severity) -> Option<Severity> {
+        match self.rules.get(rule) {
+            None => Some(default),
+            Some(RuleLevel::Off) => None,
+            Some(RuleLevel::Warning) => Some(Severity::Warning),
+            Some(RuleLevel::Error) => Some(Severity::Error),
+        }
+    }
+}
+
+impl FromStr for Config {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut config: Config = toml::from_str(s)?;
+        config.check.prepare()?;
+        config.platforms = PlatformRegistry::with_overrides(&config.platform_overrides)?;
+        Ok(config)
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        debug!("Loading config: {:?}", path);
+        let s = std::fs::read_to_string(path)?;
+        let mut config =
+            Self::from_str(&s).map_err(|e| anyhow!("Failed to load {:?}: {}", path, e))?;
+        config.base_dir = parent_dir(path);
+        config.load_data()?;
+        Ok(config)
+    }
+
+    /// Load the explicitly given config, or `.suimu.toml` next to the CSV
+    /// file if it exists, or the defaults.
+    pub fn discover(explicit: Option<&Path>, csv_file: &Path) -> Result<Self> {
+        if let Some(path) = explicit {
+            return Self::load(path);
+        }
+        let base_dir = parent_dir(csv_file);
+        let path = base_dir.join(CONFIG_FILE);
+        if path.exists() {
+            Self::load(&path)
+        } else {
+            let mut config = Self {
+                base_dir,
+                ..Self::default()
+            };
+            config.load_data()?;
+            Ok(config)
+        }
+    }
+
+    pub fn resolve(&self, path: &Path) -> PathBuf {
+        self.base_dir.join(path)
+    }
+
+    pub fn suppression_path(&self) -> PathBuf {
+        self.resolve(&self.check.suppression_file)
+    }
+
+    /// Load the data files referred to by the config, if they exist.
+    fn load_data(&mut self) -> Result<()> {
+        let path = self.suppression_path();
+        if path.exists() {
+            debug!("Loading suppressions: {:?}", path);
+            self.suppressions = Suppressions::load(&path)?;
+        }
+        let path = self.resolve(&self.artists_file);
+        if path.exists() {
+            debug!("Loading artists: {:?}", path);
+            self.artists = ArtistRegistry::load(&path)?;
+        }
+        Ok(())
+    }
+}
+
+fn parent_dir(path: &Path) -> PathBuf {
+    path.parent().map(Path::to_path_buf).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config() {
+        let config = Config::from_str(
+            r#"
+[check]
+similarity_threshold = 0.9
+title_patterns = [" ?-.+-$"]
+
+[check.rules]
+similar-values = "off"
+clip-gap = "error"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.check.similarity_threshold, 0.9);
+        assert_eq!(config.check.suspicious_gap, 1.0);
+        assert!(!config.check.is_enabled("similar-values"));
+        assert_eq!(
+            config.check.severity("clip-gap", Severity::Warning),
+            Some(Severity::Error)
+        );
+        assert_eq!(
+            config.check.severity("not-nfc", Severity::Error),
+            Some(Severity::Error)
+        );
+        assert_eq!(config.check.title_regexes().len(), 1);
+        assert_eq!(config.check.offset(), FixedOffset::east(9 * 3600));
+
+        let config =
+            Config::from_str("[check]\nutc_offset = \"+00:00\"\nearliest_date = \"2018-03-22\"")
+                .unwrap();
+        assert_eq!(config.check.offset(), FixedOffset::east(0));
+        assert_eq!(
+            config.check.earliest_date(),
+            Some(NaiveDate::from_ymd(2018, 3, 22))
+        );
+        assert!(Config::from_str("[check]\nutc_offset = \"JST\"").is_err());
+
+        let config = Config::from_str(
+            "[platforms.VIMEO]\nurl_template = \"https://vimeo.com/{}\"\nsource_ext = \"mp4\"",
+        )
+        .unwrap();
+        assert!(config.platforms.parse("VIMEO").is_some());
+
+        assert!(Config::from_str("[check]\ntitle_patterns = [\"(\"]").is_err());
+        assert!(Config::from_str("[check]\nunknown = 1").is_err());
+    }
+}
@@ -1,5 +1,9 @@
 pub mod command;
+pub mod config;
+pub mod library;
+pub mod lint;
 pub mod utils;
 
 pub use command::*;
+pub use library::{Library, Query};
 pub use utils::{MaybeMusic, Music, Platform};
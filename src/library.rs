@@ -0,0 +1,411 @@
+//! A loaded CSV library, converted to [`Music`] and queryable.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Result};
+use chrono::{DateTime, FixedOffset};
+
+use crate::config::Config;
+use crate::utils::{write_csv_rows, Row, RowError, Rows};
+use crate::{Music, Platform};
+
+/// Rows of one or more CSV files along with their conversion to [`Music`].
+///
+/// Rows failing to convert are kept with their error instead of failing the
+/// whole library. Lines are numbered across files, see [`Library::locate`].
+#[derive(Default)]
+pub struct Library {
+    files: Vec<SourceFile>,
+    rows: Vec<Row>,
+    /// Records which couldn't be read.
+    malformed: Vec<RowError>,
+    musics: Vec<Music>,
+    /// Line of each music.
+    lines: Vec<u64>,
+    /// Index into `rows` of each row failing to convert.
+    errors: Vec<(usize, anyhow::Error)>,
+    /// Index into `musics` by hash. The first music wins on a collision.
+    index: HashMap<String, usize>,
+    /// Last line in use, which the next merged file starts after.
+    end: u64,
+}
+
+/// A CSV file merged into a [`Library`].
+#[derive(Clone, Debug, Default)]
+pub struct SourceFile {
+    /// Empty for a library parsed from memory.
+    pub path: PathBuf,
+    pub headers: Vec<String>,
+    /// Added to the line of each row of the file.
+    offset: u64,
+}
+
+impl Library {
+    pub fn open(path: &Path, config: &Config) -> Result<Self> {
+        ensure!(path.exists(), format!("{:?} does not exists", path));
+        Ok(Self::from_reader(File::open(path)?, config)?.with_path(path))
+    }
+
+    /// Load several files as a single library, in order.
+    pub fn open_all(paths: &[PathBuf], config: &Config) -> Result<Self> {
+        let mut ret = Self::default();
+        for path in paths {
+            ret.merge(Self::open(path, config)?);
+        }
+        Ok(ret)
+    }
+
+    /// Load every readable row of `source`. Fails only when the headers
+    /// can't be read.
+    pub fn from_reader(source: impl Read, config: &Config) -> Result<Self> {
+        let reader = Rows::new(source)?;
+        let headers = reader.headers().iter().map(str::to_string).collect();
+        let mut rows = vec![];
+        let mut malformed = vec![];
+        for x in reader {
+            match x {
+                Ok(row) => rows.push(row),
+                Err(e) => malformed.push(e),
+            }
+        }
+        let mut ret = Self::from_rows(headers, rows, config);
+        ret.end = malformed.iter().map(|x| x.line).fold(ret.end, u64::max);
+        ret.malformed = malformed;
+        Ok(ret)
+    }
+
+    pub fn parse(source: &str, config: &Config) -> Result<Self> {
+        Self::from_reader(source.as_bytes(), config)
+    }
+
+    pub fn from_rows(headers: Vec<String>, rows: Vec<Row>, config: &Config) -> Self {
+        let mut musics = vec![];
+        let mut lines = vec![];
+        let mut errors = vec![];
+        let mut index = HashMap::new();
+        for (idx, row) in rows.iter().enumerate() {
+            match Music::convert(row.music.clone(), config) {
+                Ok(x) => {
+                    index.entry(x.xxhash.clone()).or_insert(musics.len());
+                    musics.push(x);
+                    lines.push(row.line);
+                }
+                Err(e) => errors.push((idx, e)),
+            }
+        }
+        // The header line at least
+        let end = rows.iter().map(|x| x.line).fold(1, u64::max);
+        Self {
+            files: vec![SourceFile {
+                path: PathBuf::new(),
+                headers,
+                offset: 0,
+            }],
+            rows,
+            malformed: vec![],
+            musics,
+            lines,
+            errors,
+            index,
+            end,
+        }
+    }
+
+    /// Name the files of the library, e.g. after parsing it from memory.
+    pub fn with_path(mut self, path: &Path) -> Self {
+        for x in &mut self.files {
+            x.path = path.to_path_buf();
+        }
+        self
+    }
+
+    /// Append the files of `other`, numbering their lines after ours.
+    pub fn merge(&mut self, other: Library) {
+        let offset = self.end;
+        let rows = self.rows.len();
+        let musics = self.musics.len();
+        self.files.extend(other.files.into_iter().map(|mut x| {
+            x.offset += offset;
+            x
+        }));
+        self.rows.extend(other.rows.into_iter().map(|mut x| {
+            x.line += offset;
+            x
+        }));
+        self.malformed
+            .extend(other.malformed.into_iter().map(|mut x| {
+                x.line += offset;
+                x
+            }));
+        self.musics.extend(other.musics);
+        self.lines.extend(other.lines.iter().map(|x| x + offset));
+        self.errors
+            .extend(other.errors.into_iter().map(|(idx, e)| (idx + rows, e)));
+        for (hash, idx) in other.index {
+            self.index.entry(hash).or_insert(idx + musics);
+        }
+        self.end = offset + other.end;
+    }
+
+    pub fn files(&self) -> &[SourceFile] {
+        &self.files
+    }
+
+    /// Index of the file a library line belongs to, and the line in that
+    /// file.
+    pub fn locate(&self, line: u64) -> (usize, u64) {
+        let idx = self
+            .files
+            .iter()
+            .rposition(|x| x.offset < line)
+            .unwrap_or(0);
+        (idx, line - self.files.get(idx).map_or(0, |x| x.offset))
+    }
+
+    pub fn rows(&self) -> &[Row] {
+        &self.rows
+    }
+
+    /// Records which couldn't be read, e.g. with a wrong number of fields.
+    pub fn malformed(&self) -> &[RowError] {
+        &self.malformed
+    }
+
+    /// Rows successfully converted.
+    pub fn musics(&self) -> &[Music] {
+        &self.musics
+    }
+
+    /// Line of each of [`Library::musics`].
+    pub fn lines(&self) -> &[u64] {
+        &self.lines
+    }
+
+    /// Rows failing to convert, along with the reason.
+    pub fn errors(&self) -> impl Iterator<Item = (&Row, &anyhow::Error)> {
+        self.errors
+            .iter()
+            .map(move |(idx, e)| (&self.rows[*idx], e))
+    }
+
+    /// Write the rows of a file back, with extra columns. Malformed records
+    /// are lost.
+    pub fn write(&self, file: usize, writer: impl Write) -> Result<()> {
+        let rows = self.rows.iter().filter(|x| self.locate(x.line).0 == file);
+        write_csv_rows(writer, &self.files[file].headers, rows)
+    }
+
+    pub fn get(&self, hash: &str) -> Option<&Music> {
+        self.index.get(hash).map(|&idx| &self.musics[idx])
+    }
+
+    pub fn query(&self) -> Query<'_> {
+        Query {
+            library: self,
+            filters: vec![],
+        }
+    }
+}
+
+/// CSV files listed in a manifest, one per line and relative to it. Empty
+/// lines and lines starting with `#` are ignored.
+pub fn read_manifest(path: &Path) -> Result<Vec<PathBuf>> {
+    ensure!(path.exists(), format!("{:?} does not exists", path));
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    Ok(std::fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|x| !x.is_empty() && !x.starts_with('#'))
+        .map(|x| base.join(x))
+        .collect())
+}
+
+type Filter<'a> = Box<dyn Fn(&Music) -> bool + 'a>;
+
+/// Musics of a [`Library`] matching every filter added.
+pub struct Query<'a> {
+    library: &'a Library,
+    filters: Vec<Filter<'a>>,
+}
+
+impl<'a> Query<'a> {
+    fn filter(mut self, f: impl Fn(&Music) -> bool + 'a) -> Self {
+        self.filters.push(Box::new(f));
+        self
+    }
+
+    /// Musics with `name` among their performers.
+    pub fn performer(self, name: &'a str) -> Self {
+        self.filter(move |x| x.performers.iter().any(|x| x == name))
+    }
+
+    /// Musics with `name` among their artists.
+    pub fn artist(self, name: &'a str) -> Self {
+        self.filter(move |x| x.artists.iter().any(|x| x == name))
+    }
+
+    pub fn platform(self, platform: Platform) -> Self {
+        self.filter(move |x| x.video_type == platform)
+    }
+
+    /// Musics from the source video `video_id`, on any page.
+    pub fn video(self, video_id: &'a str) -> Self {
+        self.filter(move |x| x.video_id == video_id)
+    }
+
+    /// Musics performed from `from` (inclusive) until `to` (exclusive).
+    pub fn between(self, from: DateTime<FixedOffset>, to: DateTime<FixedOffset>) -> Self {
+        self.filter(move |x| from <= x.datetime && x.datetime < to)
+    }
+
+    /// Musics tagged with `tag`.
+    pub fn tag(self, tag: &'a str) -> Self {
+        self.filter(move |x| x.tags.contains(tag))
+    }
+
+    /// Musics not tagged with `tag`.
+    pub fn without_tag(self, tag: &'a str) -> Self {
+        self.filter(move |x| !x.tags.contains(tag))
+    }
+
+    /// Musics with every bit of `flags` set in their status.
+    pub fn with_status(self, flags: u16) -> Self {
+        self.filter(move |x| x.status & flags == flags)
+    }
+
+    /// Musics with no bit of `flags` set in their status.
+    pub fn without_status(self, flags: u16) -> Self {
+        self.filter(move |x| x.status & flags == 0)
+    }
+
+    pub fn musics(&self) -> Vec<&'a Music> {
+        self.library
+            .musics
+            .iter()
+            .filter(|x| self.filters.iter().all(|f| f(x)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str =
+        "datetime,video_type,video_id,clip_start,clip_end,status,title,artist,performer,comment,tags
+2021-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBY,,,0,Bluerose,星街すいせい,星街すいせい,,original
+2018-03-27T20:54+09:00,TWITTER,978601113791299585,,,8,Starduster,ジミーサムP,星街すいせい,,
+2019-05-19T21:00+09:00,YOUTUBE,abcdefghijk,10,200,0,Title,A,星街すいせい、さくらみこ,,collab
+2019-05-19T21:00+09:00,YOUTUBE,,,,,Skipped,A,B,,
+2019-05-19T21:00+09:00,YOUTUBE,abcdefghijk
+";
+
+    #[test]
+    fn test_library() {
+        let library = Library::parse(SOURCE, &Config::default()).unwrap();
+        assert_eq!(library.files()[0].headers[0], "datetime");
+        assert_eq!(library.rows().len(), 4);
+        assert_eq!(library.musics().len(), 3);
+        assert_eq!(library.lines(), [2, 3, 4]);
+        assert_eq!(
+            library
+                .errors()
+                .map(|(row, _)| row.line)
+                .collect::<Vec<_>>(),
+            [5]
+        );
+        assert_eq!(
+            library
+                .malformed()
+                .iter()
+                .map(|x| x.line)
+                .collect::<Vec<_>>(),
+            [6]
+        );
+
+        let hash = &library.musics()[1].xxhash;
+        assert_eq!(library.get(hash).unwrap().title, "Starduster");
+        assert!(library.get("0000000000000000").is_none());
+    }
+
+    #[test]
+    fn test_extra_columns() {
+        let source = "datetime,video_type,video_id,clip_start,clip_end,status,title,lyrics,artist,performer,comment
+2021-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBY,,,0,Bluerose,\"a, b\",星街すいせい,星街すいせい,
+";
+        let library = Library::parse(source, &Config::default()).unwrap();
+        assert_eq!(library.musics()[0].extra["lyrics"], "a, b");
+        assert_eq!(library.musics()[0].artist, "星街すいせい");
+
+        let mut out = vec![];
+        library.write(0, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), source);
+    }
+
+    #[test]
+    fn test_merge() {
+        let config = Config::default();
+        let mut library = Library::parse(SOURCE, &config).unwrap();
+        let other = Library::parse(
+            "datetime,video_type,video_id,clip_start,clip_end,status,title,artist,performer,comment
+2022-07-22T00:00+09:00,YOUTUBE,AAvVx0QRtkU,,,0,Stellar Stellar,星街すいせい,星街すいせい,
+",
+            &config,
+        )
+        .unwrap();
+        let hash = other.musics()[0].xxhash.clone();
+        library.merge(other.with_path(Path::new("2022.csv")));
+
+        assert_eq!(library.files().len(), 2);
+        assert_eq!(library.files()[1].path, Path::new("2022.csv"));
+        assert_eq!(library.lines(), [2, 3, 4, 8]);
+        assert_eq!(library.locate(4), (0, 4));
+        assert_eq!(library.locate(8), (1, 2));
+        assert_eq!(library.get(&hash).unwrap().title, "Stellar Stellar");
+        assert_eq!(library.query().video("AAvVx0QRtkU").musics().len(), 1);
+    }
+
+    #[test]
+    fn test_query() {
+        let library = Library::parse(SOURCE, &Config::default()).unwrap();
+        let titles = |x: Query| {
+            x.musics()
+                .iter()
+                .map(|x| x.title.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(titles(library.query()).len(), 3);
+        assert_eq!(titles(library.query().performer("さくらみこ")), ["Title"]);
+        assert_eq!(
+            titles(library.query().artist("ジミーサムP")),
+            ["Starduster"]
+        );
+        assert_eq!(
+            titles(library.query().platform(Platform::Twitter)),
+            ["Starduster"]
+        );
+        assert_eq!(titles(library.query().video("ZfDYRy17CBY")), ["Bluerose"]);
+        assert_eq!(titles(library.query().with_status(8)), ["Starduster"]);
+        assert_eq!(titles(library.query().tag("original")), ["Bluerose"]);
+        assert_eq!(
+            titles(library.query().without_tag("original")),
+            ["Starduster", "Title"]
+        );
+        assert_eq!(
+            titles(library.query().without_status(8).performer("星街すいせい")),
+            ["Bluerose", "Title"]
+        );
+
+        let date = |x| DateTime::parse_from_rfc3339(x).unwrap();
+        assert_eq!(
+            titles(library.query().between(
+                date("2019-01-01T00:00:00+09:00"),
+                date("2021-06-25T22:30:00+09:00")
+            )),
+            ["Title"]
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Another CSV line involved in a finding, e.g. the first occurrence of a
+/// duplicated row.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Related {
+    /// Set when the line is in another file than the diagnostic.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    pub line: u64,
+    pub label: String,
+}
+
+/// A single finding of `check`, pointing at a line (and optionally a field)
+/// of the CSV file.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Diagnostic {
+    #[serde(rename = "rule")]
+    pub code: &'static str,
+    pub severity: Severity,
+    /// File of `line` when checking several files, filled by
+    /// [`check_files`](super::check_files), or a file other than the CSV.
+    #[serde(skip)]
+    pub file: Option<String>,
+    /// 1-based line the offending record starts on.
+    pub line: u64,
+    /// 1-based character column of `field` in `line`, filled by
+    /// [`locate`](super::locate).
+    pub column: Option<usize>,
+    pub field: Option<&'static str>,
+    pub message: String,
+    pub suggestion: Option<String>,
+    pub related: Vec<Related>,
+}
+
+impl Diagnostic {
+    pub fn new(code: &'static str, severity: Severity, line: u64, message: String) -> Self {
+        Self {
+            code,
+            severity,
+            file: None,
+            line,
+            column: None,
+            field: None,
+            message,
+            suggestion: None,
+            related: vec![],
+        }
+    }
+
+    pub fn error(code: &'static str, line: u64, message: impl Into<String>) -> Self {
+        Self::new(code, Severity::Error, line, message.into())
+    }
+
+    pub fn warning(code: &'static str, line: u64, message: impl Into<String>) -> Self {
+        Self::new(code, Severity::Warning, line, message.into())
+    }
+
+    pub fn with_field(mut self, field: &'static str) -> Self {
+        self.field = Some(field);
+        self
+    }
+
+    pub fn with_suggestion(mut self, suggestion: impl Into<String>) -> Self {
+        self.suggestion = Some(suggestion.into());
+        self
+    }
+
+    pub fn with_file(mut self, file: impl Into<String>) -> Self {
+        self.file = Some(file.into());
+        self
+    }
+
+    pub fn with_related(mut self, line: u64, label: impl Into<String>) -> Self {
+        self.related.push(Related {
+            file: None,
+            line,
+            label: label.into(),
+        });
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
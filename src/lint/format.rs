@@ -0,0 +1,192 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+use structopt::clap::crate_version;
+
+use super::{Diagnostic, Severity};
+
+#[derive(Clone, Copy, Debug, PartialEq, strum_macros::EnumString, strum_macros::AsRefStr)]
+#[strum(serialize_all = "lowercase")]
+pub enum OutputFormat {
+    /// Annotated snippets for humans
+    Human,
+    Json,
+    Sarif,
+    /// GitHub Actions workflow commands
+    Github,
+}
+
+#[derive(Serialize)]
+struct JsonDiagnostic<'a> {
+    file: &'a str,
+    #[serde(flatten)]
+    diagnostic: &'a Diagnostic,
+}
+
+/// A JSON array of diagnostics, each tagged with the file it belongs to.
+pub fn to_json(diagnostics: &[Diagnostic], path: &str) -> String {
+    let list = diagnostics
+        .iter()
+        .map(|diagnostic| JsonDiagnostic {
+            file: diagnostic.file.as_deref().unwrap_or(path),
+            diagnostic,
+        })
+        .collect::<Vec<_>>();
+    serde_json::to_string(&list).unwrap()
+}
+
+fn sarif_location(path: &str, line: u64, column: Option<usize>) -> Value {
+    let mut region = json!({ "startLine": line });
+    if let Some(column) = column {
+        region["startColumn"] = json!(column);
+    }
+    json!({
+        "physicalLocation": {
+            "artifactLocation": { "uri": path },
+            "region": region,
+        }
+    })
+}
+
+/// A SARIF 2.1.0 log with a single run.
+pub fn to_sarif(diagnostics: &[Diagnostic], path: &str) -> String {
+    let mut rules = diagnostics.iter().map(|x| x.code).collect::<Vec<_>>();
+    rules.sort_unstable();
+    rules.dedup();
+
+    let results = diagnostics
+        .iter()
+        .map(|x| {
+            let mut message = x.message.clone();
+            if let Some(suggestion) = &x.suggestion {
+                message = format!("{} (help: {})", message, suggestion);
+            }
+            let file = x.file.as_deref().unwrap_or(path);
+            json!({
+                "ruleId": x.code,
+                "level": match x.severity {
+                    Severity::Error => "error",
+                    Severity::Warning => "warning",
+                },
+                "message": { "text": message },
+                "locations": [sarif_location(file, x.line, x.column)],
+                "relatedLocations": x.related.iter().enumerate().map(|(id, related)| {
+                    let file = related.file.as_deref().unwrap_or(file);
+                    let mut location = sarif_location(file, related.line, None);
+                    location["id"] = json!(id);
+                    location["message"] = json!({ "text": related.label });
+                    location
+                }).collect::<Vec<_>>(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "suimu",
+                    "version": crate_version!(),
+                    "informationUri": "https://github.com/suisei-cn/suimu",
+                    "rules": rules.iter().map(|x| json!({ "id": x })).collect::<Vec<_>>(),
+                }
+            },
+            "results": results,
+        }]
+    })
+    .to_string()
+}
+
+fn escape_data(s: &str) -> String {
+    s.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(s: &str) -> String {
+    escape_data(s).replace(':', "%3A").replace(',', "%2C")
+}
+
+/// GitHub Actions workflow commands, one annotation per line.
+pub fn to_github(diagnostics: &[Diagnostic], path: &str) -> String {
+    diagnostics
+        .iter()
+        .map(|x| {
+            let mut properties = vec![
+                format!(
+                    "file={}",
+                    escape_property(x.file.as_deref().unwrap_or(path))
+                ),
+                format!("line={}", x.line),
+            ];
+            if let Some(column) = x.column {
+                properties.push(format!("col={}", column));
+            }
+            properties.push(format!("title={}", escape_property(x.code)));
+            let mut message = x.message.clone();
+            if let Some(suggestion) = &x.suggestion {
+                message = format!("{}\nhelp: {}", message, suggestion);
+            }
+            format!(
+                "::{} {}::{}\n",
+                x.severity,
+                properties.join(","),
+                escape_data(&message)
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<Diagnostic> {
+        let mut diag = Diagnostic::error("surrounding-spaces", 2, "Spaces around title")
+            .with_field("title")
+            .with_suggestion("change to 'Bluerose'");
+        diag.column = Some(48);
+        vec![
+            diag,
+            Diagnostic::warning("duplicate-row", 3, "Duplicate entry")
+                .with_related(2, "first occurrence here"),
+        ]
+    }
+
+    #[test]
+    fn test_to_json() {
+        let value: Value = serde_json::from_str(&to_json(&sample(), "a.csv")).unwrap();
+        assert_eq!(value[0]["file"], "a.csv");
+        assert_eq!(value[0]["rule"], "surrounding-spaces");
+        assert_eq!(value[0]["severity"], "error");
+        assert_eq!(value[0]["field"], "title");
+        assert_eq!(value[1]["related"][0]["line"], 2);
+    }
+
+    #[test]
+    fn test_to_sarif() {
+        let value: Value = serde_json::from_str(&to_sarif(&sample(), "a.csv")).unwrap();
+        let results = &value["runs"][0]["results"];
+        assert_eq!(results[0]["level"], "error");
+        assert_eq!(
+            results[0]["locations"][0]["physicalLocation"]["region"]["startColumn"],
+            48
+        );
+        assert_eq!(
+            value["runs"][0]["tool"]["driver"]["rules"],
+            json!([{ "id": "duplicate-row" }, { "id": "surrounding-spaces" }])
+        );
+    }
+
+    #[test]
+    fn test_to_github() {
+        assert_eq!(
+            to_github(&sample(), "a.csv"),
+            "::error file=a.csv,line=2,col=48,title=surrounding-spaces::Spaces around \
+             title%0Ahelp: change to 'Bluerose'
+::warning file=a.csv,line=3,title=duplicate-row::Duplicate entry
+"
+        );
+    }
+}
@@ -0,0 +1,570 @@
+//! Lints run by `check`, reported as line-aware [`Diagnostic`]s.
+
+mod diagnostic;
+mod format;
+mod normalize;
+mod render;
+mod rules;
+mod similarity;
+mod suppress;
+
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use csv::Reader;
+pub use diagnostic::{Diagnostic, Related, Severity};
+pub use format::{to_github, to_json, to_sarif, OutputFormat};
+use log::info;
+pub use normalize::{difference, normalize, Difference, Fold};
+pub use render::{field_spans, render};
+pub use similarity::{
+    cluster_diagnostic, find_clusters, similar_clusters, similarity_ratio, similarity_values,
+    Cluster,
+};
+pub use suppress::{Suppression, Suppressions, SUPPRESSION_FILE};
+
+use crate::config::Config;
+use crate::Library;
+
+/// Codes of every rule, which can be configured in `[check.rules]`.
+pub const RULES: &[&str] = &[
+    "malformed-row",
+    "empty-video-type",
+    "unsupported-platform",
+    "invalid-video-id",
+    "surrounding-spaces",
+    "not-nfc",
+    "non-canonical-artist",
+    "unknown-variant",
+    "unknown-tag",
+    "unexpected-offset",
+    "future-datetime",
+    "datetime-too-early",
+    "inconsistent-precision",
+    "snowflake-mismatch",
+    "duplicate-row",
+    "hash-collision",
+    "similar-values",
+    "stale-suppression",
+    "skipped-entry",
+    "conversion-failed",
+    "clip-order",
+    "zero-length-clip",
+    "clip-overlap",
+    "clip-gap",
+];
+
+/// Outcome of checking a library.
+pub struct Report {
+    pub diagnostics: Vec<Diagnostic>,
+    pub library: Library,
+}
+
+impl Report {
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(Diagnostic::is_error)
+    }
+}
+
+/// Run every enabled check on a library. Diagnostics are sorted by line,
+/// with their severity set according to `config`.
+pub fn check_library(library: &Library, config: &Config) -> Vec<Diagnostic> {
+    let rows = library.rows();
+    let mut out = vec![];
+
+    info!("Checking malformed rows...");
+    rules::check_malformed(library, &mut out);
+
+    info!("Checking entry support...");
+    rules::check_support(rows, config, &mut out);
+
+    info!("Checking video IDs...");
+    rules::check_video_ids(rows, config, &mut out);
+
+    info!("Checking potential typos...");
+    rules::check_spaces(rows, &mut out);
+
+    info!("Checking Unicode NFC conformity...");
+    rules::check_nfc(rows, &mut out);
+
+    if !config.artists.is_empty() {
+        info!("Checking canonical artist names...");
+        rules::check_artists(rows, config, &mut out);
+    }
+
+    info!("Checking title variants...");
+    rules::check_variants(rows, config, &mut out);
+
+    info!("Checking tags...");
+    rules::check_tags(rows, &config.check, &mut out);
+
+    info!("Checking datetimes...");
+    rules::check_datetimes(rows, &config.check, Utc::now(), &mut out);
+
+    info!("Checking tweet datetimes...");
+    rules::check_snowflakes(rows, &config.check, &mut out);
+
+    info!("Checking duplicate entries...");
+    rules::check_duplicates(rows, &mut out);
+
+    if config.check.is_enabled("similar-values") {
+        info!("Check similar metadatas...");
+        rules::check_similarity(rows, config, &mut out);
+
+        info!("Checking stale suppressions...");
+        rules::check_stale_suppressions(rows, config, &mut out);
+    }
+
+    info!("Validating fields...");
+    rules::check_conversion(library, &mut out);
+
+    info!("Checking entry logic...");
+    rules::check_logic(library.musics(), library.lines(), &mut out);
+
+    info!("Checking clip ranges...");
+    rules::check_clips(library.musics(), library.lines(), &config.check, &mut out);
+
+    let mut out = out
+        .into_iter()
+        .filter_map(|mut x| {
+            x.severity = config.check.severity(x.code, x.severity)?;
+            Some(x)
+        })
+        .collect::<Vec<_>>();
+    // Diagnostics in other files than the CSV go last
+    out.sort_by_key(|x| (x.file.is_some(), x.line));
+    out
+}
+
+/// Check a CSV source, locating each diagnostic in it.
+pub fn check(source: &str, config: &Config) -> Result<Report> {
+    check_files(&[(Path::new(""), source)], config)
+}
+
+/// Check CSV sources as a single library, so that duplicates are found
+/// across files. Diagnostics name their file when there are several.
+pub fn check_files(files: &[(&Path, &str)], config: &Config) -> Result<Report> {
+    let mut library = Library::default();
+    for (path, source) in files {
+        let x = Library::parse(source, config).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        library.merge(x.with_path(path));
+    }
+    let mut diagnostics = check_library(&library, config);
+
+    let multiple = files.len() > 1;
+    let name = |idx: usize| Some(files[idx].0.display().to_string()).filter(|_| multiple);
+    for diag in diagnostics.iter_mut().filter(|x| x.file.is_none()) {
+        let (file, line) = library.locate(diag.line);
+        diag.file = name(file);
+        diag.line = line;
+        for related in &mut diag.related {
+            let (other, line) = library.locate(related.line);
+            related.file = name(other).filter(|_| other != file);
+            related.line = line;
+        }
+    }
+    for (idx, (_, source)) in files.iter().enumerate() {
+        let diags = diagnostics.iter_mut().filter(|x| x.file == name(idx));
+        locate(diags, source, &library.files()[idx].headers);
+    }
+    Ok(Report {
+        diagnostics,
+        library,
+    })
+}
+
+pub fn read_headers(source: &str) -> Result<Vec<String>> {
+    let mut reader = Reader::from_reader(source.as_bytes());
+    Ok(reader.headers()?.iter().map(str::to_string).collect())
+}
+
+/// Fill the column of diagnostics pointing at a field.
+pub fn locate<'a>(
+    diagnostics: impl IntoIterator<Item = &'a mut Diagnostic>,
+    source: &str,
+    headers: &[String],
+) {
+    let lines = source.lines().collect::<Vec<_>>();
+    for diag in diagnostics {
+        let column = diag.field.and_then(|field| {
+            let index = headers.iter().position(|x| x == field)?;
+            let line = lines.get((diag.line as usize).checked_sub(1)?)?;
+            let span = field_spans(line).into_iter().nth(index)?;
+            Some(line[..span.start].chars().count() + 1)
+        });
+        diag.column = column;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::ArtistRegistry;
+
+    #[test]
+    fn test_check_files() {
+        let header = "datetime,video_type,video_id,clip_start,clip_end,status,title,artist,performer,comment\n";
+        let one = format!(
+            "{}2021-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBY,,,0,Bluerose,星街すいせい,星街すいせい,\n",
+            header
+        );
+        let two = format!(
+            "{}2018-03-27T20:54+09:00,TWITTER,978601113791299585,,,0,Starduster ,ジミーサムP,星街すいせい,
+2021-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBY,,,0,Bluerose,星街すいせい,星街すいせい,\n",
+            header
+        );
+        let report = check_files(
+            &[(Path::new("2021.csv"), &one), (Path::new("2018.csv"), &two)],
+            &Config::default(),
+        )
+        .unwrap();
+        assert_eq!(report.library.musics().len(), 3);
+        assert_eq!(
+            report
+                .diagnostics
+                .iter()
+                .map(|x| (x.file.as_deref(), x.line, x.code, x.column))
+                .collect::<Vec<_>>(),
+            [
+                (Some("2018.csv"), 2, "surrounding-spaces", Some(55)),
+                (Some("2018.csv"), 3, "duplicate-row", None),
+            ]
+        );
+        let related = &report.diagnostics[1].related[0];
+        assert_eq!(
+            (related.file.as_deref(), related.line),
+            (Some("2021.csv"), 2)
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let report = check(
+            "datetime,video_type,video_id,clip_start,clip_end,status,title,artist,performer,comment
+2021-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBY,,,0,Bluerose ,星街すいせい,星街すいせい,
+2021-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBY,,,0,Bluerose ,星街すいせい,星街すいせい,
+2021-06-25T22:30+09:00,VIMEO,76979871,,,0,Title,星街すいせい,星街すいせい,
+",
+            &Config::default(),
+        )
+        .unwrap();
+        assert!(report.has_errors());
+        assert_eq!(
+            report
+                .diagnostics
+                .iter()
+                .map(|x| (x.line, x.code, x.column))
+                .collect::<Vec<_>>(),
+            [
+                (2, "surrounding-spaces", Some(48)),
+                (3, "surrounding-spaces", Some(48)),
+                (3, "duplicate-row", None),
+                (4, "unsupported-platform", Some(24)),
+                (4, "conversion-failed", None),
+            ]
+        );
+        assert_eq!(report.library.musics().len(), 2);
+
+        let config = "[check.rules]\nsurrounding-spaces = \"off\"\nduplicate-row = \"warning\""
+            .parse::<Config>()
+            .unwrap();
+        let report = check(
+            "datetime,video_type,video_id,clip_start,clip_end,status,title,artist,performer,comment
+2021-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBY,,,0,Bluerose ,星街すいせい,星街すいせい,
+2021-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBY,,,0,Bluerose ,星街すいせい,星街すいせい,
+",
+            &config,
+        )
+        .unwrap();
+        assert!(!report.has_errors());
+        assert_eq!(
+            report
+                .diagnostics
+                .iter()
+                .map(|x| (x.line, x.code, x.severity))
+                .collect::<Vec<_>>(),
+            [(3, "duplicate-row", Severity::Warning)]
+        );
+
+        let config = Config {
+            artists: ArtistRegistry::from_toml(
+                "[[artist]]\nid = \"suisei\"\nname = \"星街すいせい\"\naliases = [\"Suisei\"]",
+            )
+            .unwrap(),
+            ..Config::default()
+        };
+        let report = check(
+            "datetime,video_type,video_id,clip_start,clip_end,status,title,artist,performer,comment
+2021-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBY,,,0,Bluerose,Suisei,星街すいせい,
+",
+            &config,
+        )
+        .unwrap();
+        assert_eq!(
+            report
+                .diagnostics
+                .iter()
+                .map(|x| (x.code, x.field, x.suggestion.as_deref()))
+                .collect::<Vec<_>>(),
+            [(
+                "non-canonical-artist",
+                Some("artist"),
+                Some("change to '星街すいせい'")
+            )]
+        );
+
+        let report = check(
+            "datetime,video_type,video_id,clip_start,clip_end,status,title,artist,performer,comment
+2021-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBY,,,0,Bluerose (Acoustic ver.),星街すいせい,星街すいせい,
+2021-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBZ,,,0,Bluerose (tv size),星街すいせい,星街すいせい,
+2021-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBX,,,0,Bluerose (Unplugged),星街すいせい,星街すいせい,
+",
+            &Config::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            report
+                .diagnostics
+                .iter()
+                .map(|x| (x.line, x.code, x.suggestion.as_deref()))
+                .collect::<Vec<_>>(),
+            [
+                (3, "unknown-variant", Some("change to 'Bluerose (TV size)'")),
+                (4, "unknown-variant", None),
+            ]
+        );
+        assert_eq!(report.library.musics()[0].base_title, "Bluerose");
+        assert_eq!(
+            report.library.musics()[0].variant.as_deref(),
+            Some("Acoustic ver.")
+        );
+        assert_eq!(
+            report.library.musics()[2].base_title,
+            "Bluerose (Unplugged)"
+        );
+        assert_eq!(report.library.musics()[2].variant, None);
+    }
+
+    #[test]
+    fn test_check_stale_suppressions() {
+        let mut config = Config::default();
+        config.suppressions.similar.push(Suppression {
+            field: "title".to_string(),
+            values: vec!["Bluerose".to_string(), "Bluerase".to_string()],
+            line: 4,
+        });
+        let report = check(
+            "datetime,video_type,video_id,clip_start,clip_end,status,title,artist,performer,comment
+2021-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBY,,,0,Bluerose,星街すいせい,星街すいせい,
+",
+            &config,
+        )
+        .unwrap();
+        assert_eq!(
+            report
+                .diagnostics
+                .iter()
+                .map(|x| (x.file.as_deref(), x.line, x.code))
+                .collect::<Vec<_>>(),
+            [(Some(SUPPRESSION_FILE), 4, "stale-suppression")]
+        );
+
+        let config = "[check.rules]\nstale-suppression = \"error\""
+            .parse::<Config>()
+            .map(|mut x| {
+                x.suppressions = config.suppressions.clone();
+                x
+            })
+            .unwrap();
+        assert!(check(
+            "datetime,video_type,video_id,clip_start,clip_end,status,title,artist,performer,comment\n",
+            &config
+        )
+        .unwrap()
+        .has_errors());
+    }
+
+    #[test]
+    fn test_check_logic() {
+        let report = check(
+            "datetime,video_type,video_id,clip_start,clip_end,status,title,artist,performer,comment
+2021-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBA,20,10,0,A,,,
+2021-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBB,10,10,0,B,,,
+",
+            &Config::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            report
+                .diagnostics
+                .iter()
+                .map(|x| (x.line, x.code, x.field, x.message.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    2,
+                    "clip-order",
+                    Some("clip_start"),
+                    "clip_start is later than clip_end"
+                ),
+                (3, "zero-length-clip", Some("clip_end"), "Zero-length clip"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_tags() {
+        let source = "datetime,video_type,video_id,clip_start,clip_end,status,title,artist,performer,comment,tags
+2021-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBA,,,0,A,,,,3D live; collab
+2021-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBB,,,0,B,,,,3d Live;karaoke
+";
+        let report = check(source, &Config::default()).unwrap();
+        assert!(report.diagnostics.is_empty());
+        assert_eq!(
+            report.library.musics()[0].tags.iter().collect::<Vec<_>>(),
+            ["3D live", "collab"]
+        );
+
+        let config = "[check]\nallowed_tags = [\"3D live\", \"collab\"]"
+            .parse::<Config>()
+            .unwrap();
+        let report = check(source, &config).unwrap();
+        assert_eq!(
+            report
+                .diagnostics
+                .iter()
+                .map(|x| (x.line, x.code, x.column, x.suggestion.as_deref()))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    3,
+                    "unknown-tag",
+                    Some(53),
+                    Some("change '3d Live' to '3D live'")
+                ),
+                (3, "unknown-tag", Some(53), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_datetimes() {
+        let config = "[check]\nearliest_date = \"2018-03-22\""
+            .parse::<Config>()
+            .unwrap();
+        let report = check(
+            "datetime,video_type,video_id,clip_start,clip_end,status,title,artist,performer,comment
+2021-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBA,,,0,A,,,
+2021-06-25T13:30+00:00,YOUTUBE,ZfDYRy17CBB,,,0,B,,,
+2999-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBC,,,0,C,,,
+2017-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBD,,,0,D,,,
+2021-06-25T22:30:00+09:00,YOUTUBE,ZfDYRy17CBE,,,0,E,,,
+",
+            &config,
+        )
+        .unwrap();
+        assert_eq!(
+            report
+                .diagnostics
+                .iter()
+                .map(|x| (x.line, x.code, x.suggestion.as_deref()))
+                .collect::<Vec<_>>(),
+            [
+                (3, "unexpected-offset", None),
+                (4, "future-datetime", None),
+                (5, "datetime-too-early", None),
+                (
+                    6,
+                    "inconsistent-precision",
+                    Some("change to '2021-06-25T22:30+09:00'")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_duplicates() {
+        // Fields are hashed without separators
+        let report = check(
+            "datetime,video_type,video_id,clip_start,clip_end,status,title,artist,performer,comment
+2021-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBY,,,0,Bluerose,星街すいせい,,
+2021-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBY,,,0,Bluerose星街,すいせい,,
+2021-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBY,,,0,Bluerose,星街すいせい,,
+",
+            &"[check.rules]\nclip-overlap = \"off\"\nsimilar-values = \"off\""
+                .parse::<Config>()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            report
+                .diagnostics
+                .iter()
+                .map(|x| (x.line, x.code))
+                .collect::<Vec<_>>(),
+            [(3, "hash-collision"), (4, "duplicate-row")]
+        );
+    }
+
+    #[test]
+    fn test_check_snowflakes() {
+        let report = check(
+            "datetime,video_type,video_id,clip_start,clip_end,status,title,artist,performer,comment
+2018-03-27T20:54+09:00,TWITTER,978601113791299585,,,0,Starduster,,,
+2018-03-28T20:54+09:00,TWITTER,978601113791299585,,,0,Starduster,,,
+",
+            &Config::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            report
+                .diagnostics
+                .iter()
+                .map(|x| (x.line, x.code, x.suggestion.as_deref()))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    3,
+                    "snowflake-mismatch",
+                    Some("change to '2018-03-27T20:54+09:00'")
+                ),
+                (3, "duplicate-row", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_video_ids() {
+        let report = check(
+            "datetime,video_type,video_id,clip_start,clip_end,status,title,artist,performer,comment
+2021-06-25T22:30+09:00,YOUTUBE,https://youtu.be/ZfDYRy17CBY,,,0,A,,,
+2021-06-25T22:30+09:00,BILIBILI,av170001,,,0,B,,,
+2021-06-25T22:30+09:00,TWITTER,suisei_hosimati,,,0,C,,,
+",
+            &Config::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            report
+                .diagnostics
+                .iter()
+                .map(|x| (x.line, x.code, x.suggestion.as_deref()))
+                .collect::<Vec<_>>(),
+            [
+                (2, "invalid-video-id", Some("change to 'ZfDYRy17CBY'")),
+                (3, "invalid-video-id", Some("change to 'BV17x411w7KC'")),
+                (4, "invalid-video-id", None),
+            ]
+        );
+        assert_eq!(
+            report
+                .library
+                .musics()
+                .iter()
+                .map(|x| x.video_id.as_str())
+                .collect::<Vec<_>>(),
+            ["ZfDYRy17CBY", "BV17x411w7KC", "suisei_hosimati"]
+        );
+    }
+}
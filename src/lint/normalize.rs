@@ -0,0 +1,169 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use unicode_normalization::UnicodeNormalization;
+
+/// A step of the normalization pipeline, applied in this order.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Fold {
+    /// NFKC, folding full-width and half-width forms
+    Width,
+    /// Hiragana to katakana
+    Kana,
+    /// Long vowel marks and dashes
+    LongVowel,
+    /// Tildes, middle dots and quotes
+    Punctuation,
+    Case,
+}
+
+const FOLDS: [Fold; 5] = [
+    Fold::Width,
+    Fold::Kana,
+    Fold::LongVowel,
+    Fold::Punctuation,
+    Fold::Case,
+];
+
+impl Fold {
+    fn name(&self) -> &'static str {
+        match self {
+            Fold::Width => "width",
+            Fold::Kana => "kana",
+            Fold::LongVowel => "long vowel marks",
+            Fold::Punctuation => "punctuation",
+            Fold::Case => "case",
+        }
+    }
+
+    pub fn apply(&self, s: &str) -> String {
+        match self {
+            Fold::Width => s.nfkc().collect(),
+            Fold::Kana => s
+                .chars()
+                .map(|c| match c {
+                    '\u{3041}'..='\u{3096}' => char::from_u32(c as u32 + 0x60).unwrap(),
+                    _ => c,
+                })
+                .collect(),
+            Fold::LongVowel => s
+                .chars()
+                .map(|c| match c {
+                    'ｰ' | '－' | '-' | '‐' | '‑' | '–' | '—' | '―' => 'ー',
+                    _ => c,
+                })
+                .collect(),
+            Fold::Punctuation => s
+                .chars()
+                .map(|c| match c {
+                    '～' | '〜' | '∼' => '~',
+                    '･' | '·' | '•' | '‧' => '・',
+                    '“' | '”' | '＂' => '"',
+                    '‘' | '’' | '＇' => '\'',
+                    _ => c,
+                })
+                .collect(),
+            Fold::Case => s.to_lowercase(),
+        }
+    }
+}
+
+/// The folds making two strings equal, as in "differs only in width".
+#[derive(Debug, PartialEq)]
+pub struct Difference(pub Vec<Fold>);
+
+impl Display for Difference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let names = self.0.iter().map(Fold::name).collect::<Vec<_>>();
+        write!(f, "differs only in {}", names.join(" and "))
+    }
+}
+
+fn apply_all<'a>(s: &str, folds: impl IntoIterator<Item = &'a Fold>) -> String {
+    folds
+        .into_iter()
+        .fold(s.to_string(), |acc, fold| fold.apply(&acc))
+}
+
+/// Run the whole normalization pipeline.
+pub fn normalize(s: &str) -> String {
+    apply_all(s, &FOLDS)
+}
+
+/// If two different strings are equal after normalization, the fewest folds
+/// making them equal. Folds overlap (width folding also maps halfwidth
+/// punctuation), so the smallest subset is searched for.
+pub fn difference(a: &str, b: &str) -> Option<Difference> {
+    if a == b || normalize(a) != normalize(b) {
+        return None;
+    }
+    let mut subsets = (1..1u32 << FOLDS.len()).collect::<Vec<_>>();
+    subsets.sort_by_key(|x| x.count_ones());
+    subsets.into_iter().find_map(|mask| {
+        let folds = FOLDS
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| mask & (1 << idx) != 0)
+            .map(|(_, x)| *x)
+            .collect::<Vec<_>>();
+        (apply_all(a, &folds) == apply_all(b, &folds)).then_some(Difference(folds))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("ＡＢＣ"), "abc");
+        assert_eq!(normalize("すいせい"), "スイセイ");
+        assert_eq!(normalize("ｽｲｾｲ"), "スイセイ");
+        assert_eq!(normalize("すいちゃ〜ん"), normalize("すいちゃ～ん"));
+        assert_eq!(normalize("A・B"), normalize("A･B"));
+        assert_eq!(normalize("ホワイト－ハッピー"), "ホワイトーハッピー");
+    }
+
+    #[test]
+    fn test_difference_overlapping() {
+        assert_eq!(
+            difference("A・B", "A･B").unwrap().to_string(),
+            "differs only in width"
+        );
+        assert_eq!(
+            difference("スｰ", "スー").unwrap().to_string(),
+            "differs only in width"
+        );
+        assert_eq!(
+            difference("すいちゃ〜ん", "すいちゃ～ん")
+                .unwrap()
+                .to_string(),
+            "differs only in punctuation"
+        );
+    }
+
+    #[test]
+    fn test_difference() {
+        assert_eq!(
+            difference("Bluerose", "Ｂｌｕｅｒｏｓｅ")
+                .unwrap()
+                .to_string(),
+            "differs only in width"
+        );
+        assert_eq!(
+            difference("すいせい", "スイセイ").unwrap().to_string(),
+            "differs only in kana"
+        );
+        assert_eq!(
+            difference("Bluerose", "ＢＬＵＥＲＯＳＥ")
+                .unwrap()
+                .to_string(),
+            "differs only in width and case"
+        );
+        assert_eq!(
+            difference("A〜B", "A～B").unwrap().to_string(),
+            "differs only in punctuation"
+        );
+        assert_eq!(difference("Bluerose", "Bluerose"), None);
+        assert_eq!(difference("Bluerose", "GHOST"), None);
+    }
+}
@@ -0,0 +1,156 @@
+use std::fmt::Write;
+use std::ops::Range;
+
+use unicode_width::UnicodeWidthStr;
+
+use super::Diagnostic;
+
+/// Byte ranges of the fields of a single CSV line, quotes included. A quoted
+/// field spanning several lines ends at the end of `line`.
+pub fn field_spans(line: &str) -> Vec<Range<usize>> {
+    let mut ret = vec![];
+    let mut start = 0;
+    let mut quoted = false;
+    for (idx, ch) in line.char_indices() {
+        match ch {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                ret.push(start..idx);
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    ret.push(start..line.len());
+    ret
+}
+
+/// Render a diagnostic as a rustc-style annotated snippet of the CSV source.
+/// `headers` is the header record, used to find the column of the field.
+/// `path` names the file unless the diagnostic has its own.
+pub fn render(diag: &Diagnostic, path: &str, source: &str, headers: &[String]) -> String {
+    let path = diag.file.as_deref().unwrap_or(path);
+    let lines = source.lines().collect::<Vec<_>>();
+    let gutter = diag
+        .related
+        .iter()
+        .map(|x| x.line)
+        .chain(std::iter::once(diag.line))
+        .max()
+        .unwrap_or(0)
+        .to_string()
+        .len();
+    let pad = " ".repeat(gutter);
+
+    let mut out = String::new();
+    writeln!(out, "{}[{}]: {}", diag.severity, diag.code, diag.message).unwrap();
+    match diag.column {
+        Some(col) => writeln!(out, "{}--> {}:{}:{}", pad, path, diag.line, col),
+        None => writeln!(out, "{}--> {}:{}", pad, path, diag.line),
+    }
+    .unwrap();
+    writeln!(out, "{} |", pad).unwrap();
+
+    // Mark the field if any, or the whole line otherwise
+    let index = diag
+        .field
+        .and_then(|field| headers.iter().position(|x| x == field));
+    let span = |line: &str| match index {
+        Some(index) => field_spans(line).into_iter().nth(index),
+        None => Some(0..line.len()),
+    };
+
+    write_line(&mut out, &lines, diag.line, gutter);
+    if let Some(line) = get_line(&lines, diag.line) {
+        if let (Some(field), Some(span)) = (diag.field, span(line)) {
+            write_marker(&mut out, &pad, line, span, '^', field);
+        }
+    }
+
+    for related in &diag.related {
+        writeln!(out, "{} |", pad).unwrap();
+        if let Some(file) = &related.file {
+            let (label, line) = (&related.label, related.line);
+            writeln!(out, "{} = note: {} in {}:{}", pad, label, file, line).unwrap();
+            continue;
+        }
+        write_line(&mut out, &lines, related.line, gutter);
+        if let Some(line) = get_line(&lines, related.line) {
+            if let Some(span) = span(line) {
+                write_marker(&mut out, &pad, line, span, '-', &related.label);
+            }
+        }
+    }
+
+    if let Some(suggestion) = &diag.suggestion {
+        writeln!(out, "{} |", pad).unwrap();
+        writeln!(out, "{} = help: {}", pad, suggestion).unwrap();
+    }
+    out
+}
+
+fn get_line<'a>(lines: &[&'a str], line: u64) -> Option<&'a str> {
+    lines.get((line as usize).checked_sub(1)?).copied()
+}
+
+fn write_line(out: &mut String, lines: &[&str], line: u64, gutter: usize) {
+    let text = get_line(lines, line).unwrap_or_default();
+    writeln!(out, "{:>width$} | {}", line, text, width = gutter).unwrap();
+}
+
+fn write_marker(
+    out: &mut String,
+    pad: &str,
+    line: &str,
+    span: Range<usize>,
+    marker: char,
+    label: &str,
+) {
+    let offset = line[..span.start].width();
+    let len = line[span].width().max(1);
+    writeln!(
+        out,
+        "{} | {}{} {}",
+        pad,
+        " ".repeat(offset),
+        marker.to_string().repeat(len),
+        label
+    )
+    .unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_spans() {
+        let line = r#"a,"b,c",,d"#;
+        let spans = field_spans(line);
+        assert_eq!(
+            spans.iter().map(|x| &line[x.clone()]).collect::<Vec<_>>(),
+            ["a", "\"b,c\"", "", "d"]
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let source = "title,artist\nBluerose ,星街すいせい\n";
+        let headers = ["title".to_string(), "artist".to_string()];
+        let mut diag = Diagnostic::error("surrounding-spaces", 2, "Spaces around title")
+            .with_field("title")
+            .with_suggestion("change to 'Bluerose'");
+        diag.column = Some(1);
+        assert_eq!(
+            render(&diag, "test.csv", source, &headers),
+            "error[surrounding-spaces]: Spaces around title
+ --> test.csv:2:1
+  |
+2 | Bluerose ,星街すいせい
+  | ^^^^^^^^^ title
+  |
+  = help: change to 'Bluerose'
+"
+        );
+    }
+}
@@ -0,0 +1,420 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Timelike, Utc};
+
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+use super::similarity::{cluster_diagnostic, similar_clusters, similarity_values};
+use super::{normalize, Diagnostic};
+use crate::config::{CheckConfig, Config};
+use crate::utils::{
+    self, find_clip_issues, find_duplicates, parse_time, snowflake_time, ClipIssue, LogicError, Row,
+};
+use crate::{Library, Music, Platform};
+
+const MINUTE_FORMAT: &str = "%Y-%m-%dT%H:%M%:z";
+const SECOND_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%:z";
+
+/// Platform support of each row, by the built-in and configured platforms.
+pub fn check_support(rows: &[Row], config: &Config, out: &mut Vec<Diagnostic>) {
+    for row in rows {
+        let x = &row.music;
+        if x.video_type.is_empty() {
+            // Often used to skip a conversion
+            out.push(
+                Diagnostic::warning("empty-video-type", row.line, "Empty video_type")
+                    .with_field("video_type")
+                    .with_suggestion(
+                        "use 'LOCAL' with a path under the archive directory to build it",
+                    ),
+            );
+            continue;
+        }
+        if config.platforms.parse(&x.video_type).is_none() {
+            out.push(
+                Diagnostic::error(
+                    "unsupported-platform",
+                    row.line,
+                    format!("Platform '{}' is not supported", x.video_type),
+                )
+                .with_field("video_type"),
+            );
+        }
+    }
+}
+
+/// Syntax of video IDs on supported platforms.
+pub fn check_video_ids(rows: &[Row], config: &Config, out: &mut Vec<Diagnostic>) {
+    for row in rows {
+        let x = &row.music;
+        let platform = match config.platforms.parse(x.video_type.trim()) {
+            Some(platform) => platform,
+            None => continue,
+        };
+        let id = x.video_id.trim();
+        if id.is_empty() || config.platforms.is_valid_id(&platform, id) {
+            continue;
+        }
+        let mut diag = Diagnostic::error(
+            "invalid-video-id",
+            row.line,
+            format!("Invalid {} video_id '{}'", platform.as_ref(), id),
+        )
+        .with_field("video_id");
+        if let Some(fixed) = platform.extract_id(id) {
+            diag = diag.with_suggestion(format!("change to '{}'", fixed));
+        }
+        out.push(diag);
+    }
+}
+
+/// Potential typos: spaces around title and artist.
+pub fn check_spaces(rows: &[Row], out: &mut Vec<Diagnostic>) {
+    for row in rows {
+        let x = &row.music;
+        for (field, value) in [("title", &x.title), ("artist", &x.artist)] {
+            if value.trim() != value {
+                out.push(
+                    Diagnostic::error(
+                        "surrounding-spaces",
+                        row.line,
+                        format!("Spaces around {}", field),
+                    )
+                    .with_field(field)
+                    .with_suggestion(format!("change to '{}'", value.trim())),
+                );
+            }
+        }
+    }
+}
+
+/// Unicode NFC conformity of title and artist.
+pub fn check_nfc(rows: &[Row], out: &mut Vec<Diagnostic>) {
+    for row in rows {
+        let x = &row.music;
+        for (field, value) in [("title", &x.title), ("artist", &x.artist)] {
+            if !is_nfc(value) {
+                out.push(
+                    Diagnostic::error("not-nfc", row.line, format!("{} is not in NFC", field))
+                        .with_field(field)
+                        .with_suggestion(format!(
+                            "change to '{}'",
+                            value.chars().nfc().collect::<String>()
+                        )),
+                );
+            }
+        }
+    }
+}
+
+/// Names in artist and performer spelled with an alias instead of the
+/// canonical name.
+pub fn check_artists(rows: &[Row], config: &Config, out: &mut Vec<Diagnostic>) {
+    for row in rows {
+        let x = &row.music;
+        for (field, value) in [("artist", &x.artist), ("performer", &x.performer)] {
+            let names = config.credits.split(value);
+            for name in &names {
+                let artist = match config.artists.get(name) {
+                    Some(artist) if &artist.name != name => artist,
+                    _ => continue,
+                };
+                let fixed = if names.len() == 1 {
+                    artist.name.clone()
+                } else {
+                    value.replacen(name.as_str(), &artist.name, 1)
+                };
+                out.push(
+                    Diagnostic::warning(
+                        "non-canonical-artist",
+                        row.line,
+                        format!("'{}' is an alias of '{}'", name, artist.name),
+                    )
+                    .with_field(field)
+                    .with_suggestion(format!("change to '{}'", fixed)),
+                );
+            }
+        }
+    }
+}
+
+/// Variant labels in title suffixes missing from the vocabulary.
+pub fn check_variants(rows: &[Row], config: &Config, out: &mut Vec<Diagnostic>) {
+    for row in rows {
+        let title = row.music.title.trim();
+        let label = match config.variants.split(title).1 {
+            Some(label) if !config.variants.is_known(label) => label,
+            _ => continue,
+        };
+        let mut diag = Diagnostic::warning(
+            "unknown-variant",
+            row.line,
+            format!("Unknown variant label '{}'", label),
+        )
+        .with_field("title");
+        if let Some(known) = config
+            .variants
+            .labels
+            .iter()
+            .find(|x| normalize(x) == normalize(label))
+        {
+            let idx = title.rfind(label).unwrap();
+            let fixed = format!("{}{}{}", &title[..idx], known, &title[idx + label.len()..]);
+            diag = diag.with_suggestion(format!("change to '{}'", fixed));
+        }
+        out.push(diag);
+    }
+}
+
+/// Tags outside of the allowed vocabulary, if any.
+pub fn check_tags(rows: &[Row], config: &CheckConfig, out: &mut Vec<Diagnostic>) {
+    if config.allowed_tags.is_empty() {
+        return;
+    }
+    for row in rows {
+        for tag in row.music.tags() {
+            if config.allowed_tags.iter().any(|x| x == tag) {
+                continue;
+            }
+            let mut diag =
+                Diagnostic::warning("unknown-tag", row.line, format!("Unknown tag '{}'", tag))
+                    .with_field("tags");
+            if let Some(known) = config
+                .allowed_tags
+                .iter()
+                .find(|x| normalize(x) == normalize(tag))
+            {
+                diag = diag.with_suggestion(format!("change '{}' to '{}'", tag, known));
+            }
+            out.push(diag);
+        }
+    }
+}
+
+/// Datetimes with an unexpected offset, in the future, before the earliest
+/// date, or with a precision differing from most rows.
+pub fn check_datetimes(
+    rows: &[Row],
+    config: &CheckConfig,
+    now: DateTime<Utc>,
+    out: &mut Vec<Diagnostic>,
+) {
+    // Unparsable datetimes are reported by the conversion
+    let parsed = rows
+        .iter()
+        .filter_map(|row| {
+            let raw = row.music.datetime.trim();
+            let minutes = DateTime::parse_from_str(raw, MINUTE_FORMAT).is_ok();
+            parse_time(raw).ok().map(|x| (row, x, minutes))
+        })
+        .collect::<Vec<_>>();
+    let minutes = parsed.iter().filter(|(_, _, x)| *x).count();
+    let use_minutes = minutes * 2 >= parsed.len();
+
+    for (row, datetime, minutes) in parsed {
+        if datetime.offset() != &config.offset() {
+            out.push(
+                Diagnostic::warning(
+                    "unexpected-offset",
+                    row.line,
+                    format!(
+                        "Offset {} differs from {}",
+                        datetime.offset(),
+                        config.offset()
+                    ),
+                )
+                .with_field("datetime"),
+            );
+        }
+        if datetime > now {
+            out.push(
+                Diagnostic::error("future-datetime", row.line, "Datetime is in the future")
+                    .with_field("datetime"),
+            );
+        }
+        if let Some(earliest) = config.earliest_date() {
+            if datetime.naive_local().date() < earliest {
+                out.push(
+                    Diagnostic::error(
+                        "datetime-too-early",
+                        row.line,
+                        format!("Datetime is before {}", earliest),
+                    )
+                    .with_field("datetime"),
+                );
+            }
+        }
+        if minutes != use_minutes {
+            let (message, format) = if use_minutes {
+                ("Datetime has seconds unlike most rows", MINUTE_FORMAT)
+            } else {
+                ("Datetime lacks seconds unlike most rows", SECOND_FORMAT)
+            };
+            let mut diag = Diagnostic::warning("inconsistent-precision", row.line, message)
+                .with_field("datetime");
+            if !use_minutes || datetime.second() == 0 {
+                diag = diag.with_suggestion(format!("change to '{}'", datetime.format(format)));
+            }
+            out.push(diag);
+        }
+    }
+}
+
+/// Datetimes of tweets differing from the time encoded in their ID.
+pub fn check_snowflakes(rows: &[Row], config: &CheckConfig, out: &mut Vec<Diagnostic>) {
+    for row in rows {
+        let x = &row.music;
+        if Platform::from_str(x.video_type.trim()) != Ok(Platform::Twitter) {
+            continue;
+        }
+        let raw = x.datetime.trim();
+        let (datetime, tweeted) = match (
+            parse_time(raw),
+            x.video_id.trim().parse().ok().and_then(snowflake_time),
+        ) {
+            (Ok(datetime), Some(tweeted)) => (datetime, tweeted),
+            _ => continue,
+        };
+        let diff = (datetime.with_timezone(&Utc) - tweeted).num_seconds().abs();
+        if diff <= config.snowflake_window {
+            continue;
+        }
+        // Keep the precision and offset of the row
+        let format = if DateTime::parse_from_str(raw, MINUTE_FORMAT).is_ok() {
+            MINUTE_FORMAT
+        } else {
+            SECOND_FORMAT
+        };
+        let fixed = tweeted.with_timezone(datetime.offset()).format(format);
+        out.push(
+            Diagnostic::warning(
+                "snowflake-mismatch",
+                row.line,
+                format!("Datetime differs from the tweet time by {}s", diff),
+            )
+            .with_field("datetime")
+            .with_suggestion(format!("change to '{}'", fixed)),
+        );
+    }
+}
+
+/// Rows sharing the same hash, either as duplicates or by collision.
+pub fn check_duplicates(rows: &[Row], out: &mut Vec<Diagnostic>) {
+    for (first, dup) in find_duplicates(rows.iter().map(|x| &x.music)) {
+        if rows[first].music.hashed_fields() == rows[dup].music.hashed_fields() {
+            out.push(
+                Diagnostic::error("duplicate-row", rows[dup].line, "Duplicate entry")
+                    .with_related(rows[first].line, "first occurrence here"),
+            );
+        } else {
+            out.push(
+                Diagnostic::error(
+                    "hash-collision",
+                    rows[dup].line,
+                    format!(
+                        "Hash {} collides with another entry",
+                        rows[dup].music.hash()
+                    ),
+                )
+                .with_related(rows[first].line, "same hash here"),
+            );
+        }
+    }
+}
+
+/// Similar titles and artists.
+pub fn check_similarity(rows: &[Row], config: &Config, out: &mut Vec<Diagnostic>) {
+    for (field, cluster) in similar_clusters(rows, config) {
+        out.push(cluster_diagnostic(field, &cluster));
+    }
+}
+
+/// Records of a library which couldn't be read.
+pub fn check_malformed(library: &Library, out: &mut Vec<Diagnostic>) {
+    for x in library.malformed() {
+        out.push(Diagnostic::error(
+            "malformed-row",
+            x.line,
+            x.error.to_string(),
+        ));
+    }
+}
+
+/// Suppressions referring to values that no longer exist, located in the
+/// suppression file.
+pub fn check_stale_suppressions(rows: &[Row], config: &Config, out: &mut Vec<Diagnostic>) {
+    let values = similarity_values(rows, config);
+    let path = config.suppression_path().display().to_string();
+    for (x, missing) in config.suppressions.stale(&values) {
+        out.push(
+            Diagnostic::warning(
+                "stale-suppression",
+                x.line,
+                format!(
+                    "Stale suppression of similar {}s {:?}: {:?} no longer exist",
+                    x.field, x.values, missing
+                ),
+            )
+            .with_file(path.clone()),
+        );
+    }
+}
+
+/// Rows of a library failing to convert to [`Music`].
+pub fn check_conversion(library: &Library, out: &mut Vec<Diagnostic>) {
+    for (row, e) in library.errors() {
+        let message = format!("Failed to convert to music: {}", e);
+        if &e.to_string() == "No status present" {
+            // Often used to skip a conversion
+            out.push(Diagnostic::warning("skipped-entry", row.line, message).with_field("status"));
+        } else {
+            out.push(Diagnostic::error("conversion-failed", row.line, message));
+        }
+    }
+}
+
+/// Consistency of clip start & end of each music.
+pub fn check_logic(musics: &[Music], lines: &[u64], out: &mut Vec<Diagnostic>) {
+    for (x, &line) in musics.iter().zip(lines) {
+        let e = match utils::check_logic(x) {
+            Ok(()) => continue,
+            Err(e) => e,
+        };
+        let (code, field) = match e {
+            LogicError::ClipOrder => ("clip-order", "clip_start"),
+            LogicError::ZeroLengthClip => ("zero-length-clip", "clip_end"),
+        };
+        out.push(Diagnostic::error(code, line, e.to_string()).with_field(field));
+    }
+}
+
+/// Overlapping clips and suspicious gaps between clips of the same video.
+pub fn check_clips(
+    musics: &[Music],
+    lines: &[u64],
+    config: &CheckConfig,
+    out: &mut Vec<Diagnostic>,
+) {
+    for issue in find_clip_issues(musics, config.suspicious_gap) {
+        match issue {
+            ClipIssue::Overlap(one, two) => out.push(
+                Diagnostic::error(
+                    "clip-overlap",
+                    lines[two],
+                    "Clip overlaps with another clip",
+                )
+                .with_field("clip_start")
+                .with_related(lines[one], "overlapping clip here"),
+            ),
+            ClipIssue::Gap(one, two, gap) => out.push(
+                Diagnostic::warning(
+                    "clip-gap",
+                    lines[two],
+                    format!("Clip starts {}s after the end of the previous clip", gap),
+                )
+                .with_field("clip_start")
+                .with_related(lines[one], "previous clip here"),
+            ),
+        }
+    }
+}
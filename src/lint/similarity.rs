@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+
+use levenshtein::levenshtein;
+
+use super::{difference, normalize, Diagnostic};
+use crate::config::Config;
+use crate::utils::Row;
+
+/// Return the Levenshtein ratio of two strings. SHall be a value between 0 and
+/// 1.
+pub fn similarity_ratio(a: &str, b: &str) -> f32 {
+    let len = a.chars().count().max(b.chars().count());
+    1f32 - (levenshtein(a, b) as f32) / (len as f32)
+}
+
+/// A BK-tree over Levenshtein distance. Node `i` holds the `i`-th inserted
+/// value.
+struct BkTree<'a> {
+    nodes: Vec<(&'a str, Vec<(usize, usize)>)>,
+}
+
+impl<'a> BkTree<'a> {
+    fn new() -> Self {
+        Self { nodes: vec![] }
+    }
+
+    fn insert(&mut self, value: &'a str) {
+        let new = self.nodes.len();
+        self.nodes.push((value, vec![]));
+        if new == 0 {
+            return;
+        }
+        let mut cur = 0;
+        loop {
+            let dist = levenshtein(self.nodes[cur].0, value);
+            match self.nodes[cur].1.iter().find(|(d, _)| *d == dist) {
+                Some(&(_, child)) => cur = child,
+                None => {
+                    self.nodes[cur].1.push((dist, new));
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Nodes within `radius` of `value`.
+    fn find(&self, value: &str, radius: usize) -> Vec<usize> {
+        let mut ret = vec![];
+        if self.nodes.is_empty() {
+            return ret;
+        }
+        let mut stack = vec![0];
+        while let Some(cur) = stack.pop() {
+            let (node, children) = &self.nodes[cur];
+            let dist = levenshtein(node, value);
+            if dist <= radius {
+                ret.push(cur);
+            }
+            stack.extend(
+                children
+                    .iter()
+                    .filter(|(d, _)| *d + radius >= dist && *d <= dist + radius)
+                    .map(|(_, child)| *child),
+            );
+        }
+        ret
+    }
+}
+
+/// A group of distinct but similar values.
+#[derive(Debug, PartialEq)]
+pub struct Cluster {
+    /// Each value along with the lines it appears on, most common first.
+    pub values: Vec<(String, Vec<u64>)>,
+}
+
+impl Cluster {
+    /// The most common spelling.
+    pub fn canonical(&self) -> &str {
+        &self.values[0].0
+    }
+}
+
+/// Group values whose similarity ratio to another value of the group is
+/// above `threshold`, once normalized. `values` holds the line of each row
+/// along with the value to compare; empty values are ignored.
+pub fn find_clusters(values: &[(u64, impl AsRef<str>)], threshold: f32) -> Vec<Cluster> {
+    // Distinct values in order of first appearance
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    let mut distinct: Vec<(&str, Vec<u64>)> = vec![];
+    for (line, value) in values {
+        let value = value.as_ref();
+        if value.is_empty() {
+            continue;
+        }
+        let idx = *index.entry(value).or_insert_with(|| {
+            distinct.push((value, vec![]));
+            distinct.len() - 1
+        });
+        distinct[idx].1.push(*line);
+    }
+
+    let normalized = distinct
+        .iter()
+        .map(|(value, _)| normalize(value))
+        .collect::<Vec<_>>();
+    let mut tree = BkTree::new();
+    let mut parent = (0..distinct.len()).collect::<Vec<_>>();
+    fn root(parent: &mut [usize], mut x: usize) -> usize {
+        while parent[x] != x {
+            parent[x] = parent[parent[x]];
+            x = parent[x];
+        }
+        x
+    }
+
+    for (idx, value) in normalized.iter().enumerate() {
+        // sim > threshold implies max(len) < len / threshold, so the distance
+        // is bounded by (1 - threshold) * len / threshold.
+        let len = value.chars().count() as f32;
+        let radius = ((1.0 - threshold) * len / threshold.max(f32::EPSILON)).ceil() as usize;
+        for other in tree.find(value, radius) {
+            if similarity_ratio(value, &normalized[other]) > threshold {
+                let (a, b) = (root(&mut parent, idx), root(&mut parent, other));
+                parent[a.max(b)] = a.min(b);
+            }
+        }
+        tree.insert(value);
+    }
+
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    for idx in 0..distinct.len() {
+        let r = root(&mut parent, idx);
+        groups.entry(r).or_default().push(idx);
+    }
+
+    let mut ret = groups
+        .into_values()
+        .filter(|x| x.len() > 1)
+        .map(|mut members| {
+            members.sort_by_key(|&x| (std::cmp::Reverse(distinct[x].1.len()), x));
+            Cluster {
+                values: members
+                    .into_iter()
+                    .map(|x| (distinct[x].0.to_string(), distinct[x].1.clone()))
+                    .collect(),
+            }
+        })
+        .collect::<Vec<_>>();
+    ret.sort_by_key(|x| x.values.iter().map(|(_, lines)| lines[0]).min());
+    ret
+}
+
+/// Values compared by the similarity check, per field. Titles ignore their
+/// variant suffix and the configured patterns.
+pub fn similarity_values(rows: &[Row], config: &Config) -> Vec<(&'static str, Vec<(u64, String)>)> {
+    let titles = rows
+        .iter()
+        .map(|x| {
+            let mut title = config.variants.split(&x.music.title).0.to_string();
+            for re in config.check.title_regexes() {
+                title = re.replace_all(&title, "").to_string();
+            }
+            (x.line, title)
+        })
+        .collect();
+    let artists = rows
+        .iter()
+        .map(|x| (x.line, x.music.artist.clone()))
+        .collect();
+    vec![("title", titles), ("artist", artists)]
+}
+
+/// Clusters of similar values of each field, except the suppressed ones.
+pub fn similar_clusters(rows: &[Row], config: &Config) -> Vec<(&'static str, Cluster)> {
+    similarity_values(rows, config)
+        .into_iter()
+        .flat_map(|(field, values)| {
+            find_clusters(&values, config.check.similarity_threshold)
+                .into_iter()
+                .filter(|x| !config.suppressions.is_suppressed(field, x))
+                .map(move |x| (field, x))
+        })
+        .collect()
+}
+
+/// A warning for a cluster of similar values, suggesting the most common
+/// spelling. Values equal to it once normalized tell how they differ.
+pub fn cluster_diagnostic(field: &'static str, cluster: &Cluster) -> Diagnostic {
+    let summary = cluster
+        .values
+        .iter()
+        .map(
+            |(value, lines)| match difference(cluster.canonical(), value) {
+                Some(diff) => format!("'{}' ({}, {})", value, lines.len(), diff),
+                None => format!("'{}' ({})", value, lines.len()),
+            },
+        )
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut diag = Diagnostic::warning(
+        "similar-values",
+        cluster.values[1].1[0],
+        format!("Similar {}s: {}", field, summary),
+    )
+    .with_field(field)
+    .with_suggestion(format!("use '{}'", cluster.canonical()));
+    for (idx, (value, lines)) in cluster.values.iter().enumerate() {
+        if idx != 1 {
+            diag = diag.with_related(lines[0], format!("'{}' here", value));
+        }
+    }
+    diag
+}
+
+#[test]
+fn test_similarity_ratio() {
+    // Normal cases
+    assert_eq!(similarity_ratio("test", "test"), 1.0);
+    assert_eq!(similarity_ratio("abcd", "efgh"), 0.0);
+    // CJK
+    assert_eq!(similarity_ratio("双海亚美", "双海真美"), 0.75);
+    assert_eq!(similarity_ratio("中文Aka", "英文Aka"), 0.8);
+}
+
+#[test]
+fn test_find_clusters() {
+    let values = [
+        (2, "星街すいせい"),
+        (3, "Bluerose"),
+        (4, "星街すいせい"),
+        (5, "星街すいせ"),
+        (6, "BlueRose"),
+        (7, "Bluerose"),
+        (8, "NEXT COLOR PLANET"),
+        (9, ""),
+        (10, "双海亚美"),
+        (11, "双海真美"),
+        (12, "すいちゃ〜ん"),
+        (13, "ｽｲﾁｬ～ﾝ"),
+    ];
+    assert_eq!(
+        find_clusters(&values, 0.75),
+        [
+            Cluster {
+                values: vec![
+                    ("星街すいせい".to_string(), vec![2, 4]),
+                    ("星街すいせ".to_string(), vec![5]),
+                ]
+            },
+            Cluster {
+                values: vec![
+                    ("Bluerose".to_string(), vec![3, 7]),
+                    ("BlueRose".to_string(), vec![6]),
+                ]
+            },
+            Cluster {
+                values: vec![
+                    ("すいちゃ〜ん".to_string(), vec![12]),
+                    ("ｽｲﾁｬ～ﾝ".to_string(), vec![13]),
+                ]
+            },
+        ]
+    );
+    assert_eq!(find_clusters(&values, 0.7).len(), 4);
+}
+
+#[test]
+fn test_cluster_diagnostic() {
+    let cluster = Cluster {
+        values: vec![
+            ("Bluerose".to_string(), vec![3, 7]),
+            ("ＢＬＵＥＲＯＳＥ".to_string(), vec![6]),
+            ("Bluerise".to_string(), vec![8]),
+        ],
+    };
+    assert_eq!(
+        cluster_diagnostic("title", &cluster).message,
+        "Similar titles: 'Bluerose' (2), 'ＢＬＵＥＲＯＳＥ' (1, differs only in width and case), 'Bluerise' (1)"
+    );
+}
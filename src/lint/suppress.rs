@@ -0,0 +1,157 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use super::Cluster;
+
+pub const SUPPRESSION_FILE: &str = ".suimu-suppress.toml";
+
+/// Accepted similarity warnings, checked in next to the dataset.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Suppressions {
+    pub similar: Vec<Suppression>,
+}
+
+/// A group of values of `field` accepted as different. A pair is a group of
+/// two.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Suppression {
+    pub field: String,
+    pub values: Vec<String>,
+    /// Line of its `[[similar]]` header in the suppression file, or 0.
+    #[serde(skip)]
+    pub line: u64,
+}
+
+impl Suppressions {
+    pub fn load(path: &Path) -> Result<Self> {
+        let s = std::fs::read_to_string(path)?;
+        let mut ret: Self =
+            toml::from_str(&s).map_err(|e| anyhow!("Failed to load {:?}: {}", path, e))?;
+        let headers = s
+            .lines()
+            .enumerate()
+            .filter(|(_, x)| x.trim() == "[[similar]]")
+            .map(|(idx, _)| idx as u64 + 1);
+        for (x, line) in ret.similar.iter_mut().zip(headers) {
+            x.line = line;
+        }
+        Ok(ret)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Whether every value of the cluster is covered by a single suppression.
+    pub fn is_suppressed(&self, field: &str, cluster: &Cluster) -> bool {
+        self.similar.iter().any(|x| {
+            x.field == field
+                && cluster
+                    .values
+                    .iter()
+                    .all(|(value, _)| x.values.contains(value))
+        })
+    }
+
+    pub fn add(&mut self, field: &str, cluster: &Cluster) {
+        self.similar.push(Suppression {
+            field: field.to_string(),
+            values: cluster.values.iter().map(|(x, _)| x.clone()).collect(),
+            line: 0,
+        });
+    }
+
+    /// Suppressions referring to values that no longer exist, along with the
+    /// missing values. `values` holds the compared values of each field.
+    pub fn stale<'a>(
+        &'a self,
+        values: &[(&str, Vec<(u64, String)>)],
+    ) -> Vec<(&'a Suppression, Vec<&'a str>)> {
+        self.similar
+            .iter()
+            .filter_map(|x| {
+                let existing = values
+                    .iter()
+                    .filter(|(field, _)| *field == x.field)
+                    .flat_map(|(_, values)| values.iter().map(|(_, value)| value.as_str()))
+                    .collect::<HashSet<_>>();
+                let missing = x
+                    .values
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|value| !existing.contains(value))
+                    .collect::<Vec<_>>();
+                if missing.is_empty() {
+                    None
+                } else {
+                    Some((x, missing))
+                }
+            })
+            .collect()
+    }
+
+    /// Remove stale suppressions. Returns how many were removed.
+    pub fn prune(&mut self, values: &[(&str, Vec<(u64, String)>)]) -> usize {
+        let stale = self
+            .stale(values)
+            .into_iter()
+            .map(|(x, _)| x.clone())
+            .collect::<Vec<_>>();
+        self.similar.retain(|x| !stale.contains(x));
+        stale.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suppressions() {
+        let mut suppressions: Suppressions = toml::from_str(
+            r#"
+[[similar]]
+field = "title"
+values = ["双海亚美", "双海真美", "双海"]
+"#,
+        )
+        .unwrap();
+        let cluster = Cluster {
+            values: vec![
+                ("双海亚美".to_string(), vec![2]),
+                ("双海真美".to_string(), vec![3]),
+            ],
+        };
+        assert!(suppressions.is_suppressed("title", &cluster));
+        assert!(!suppressions.is_suppressed("artist", &cluster));
+
+        let values = [(
+            "title",
+            vec![(2, "双海亚美".to_string()), (3, "双海真美".to_string())],
+        )];
+        assert_eq!(suppressions.stale(&values)[0].1, ["双海"]);
+
+        let path = std::env::temp_dir().join("suimu-test-suppress.toml");
+        std::fs::write(
+            &path,
+            "[[similar]]\nfield = \"title\"\nvalues = [\"a\", \"b\"]\n\n[[similar]]\nfield = \"artist\"\nvalues = [\"c\", \"d\"]\n",
+        )
+        .unwrap();
+        let loaded = Suppressions::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            loaded.similar.iter().map(|x| x.line).collect::<Vec<_>>(),
+            [1, 5]
+        );
+
+        suppressions.add("artist", &cluster);
+        assert_eq!(suppressions.prune(&values), 2);
+        assert!(suppressions.similar.is_empty());
+    }
+}
@@ -8,12 +8,15 @@ version = clap::crate_version ! (),
 author = clap::crate_authors ! (),
 about = clap::crate_description ! ()
 )]
+#[allow(clippy::large_enum_variant)]
 enum Suimu {
     Build(BuildOpt),
     BuildInteractive,
     Check(CheckOpt),
     #[cfg(feature = "update")]
     CheckUpdate,
+    Hash(HashOpt),
+    Suppress(SuppressOpt),
 }
 
 fn main() -> Result<()> {
@@ -31,6 +34,8 @@ fn main() -> Result<()> {
         Suimu::BuildInteractive => build_interactive()?,
         #[cfg(feature = "update")]
         Suimu::CheckUpdate => check_update()?,
+        Suimu::Hash(hash_opt) => hash(hash_opt)?,
+        Suimu::Suppress(suppress_opt) => suppress(suppress_opt)?,
     }
     Ok(())
 }
@@ -0,0 +1,126 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+
+pub const ARTISTS_FILE: &str = "artists.toml";
+
+/// An artist with its canonical name and known aliases.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Artist {
+    /// Canonical artist ID, stable across renames.
+    pub id: String,
+    /// Canonical spelling used in the dataset.
+    pub name: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// IDs on external services, e.g. `musicbrainz = "..."`.
+    #[serde(default)]
+    pub external: BTreeMap<String, String>,
+}
+
+/// Registry of artists, mapping canonical names and aliases to artists.
+#[derive(Clone, Debug, Default)]
+pub struct ArtistRegistry {
+    artists: Vec<Artist>,
+    index: HashMap<String, usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ArtistFile {
+    #[serde(default)]
+    artist: Vec<Artist>,
+}
+
+impl ArtistRegistry {
+    pub fn new(artists: Vec<Artist>) -> Result<Self> {
+        let mut index = HashMap::new();
+        for (idx, artist) in artists.iter().enumerate() {
+            for name in std::iter::once(&artist.name).chain(&artist.aliases) {
+                if let Some(other) = index.insert(name.clone(), idx) {
+                    if other != idx {
+                        bail!(
+                            "'{}' refers to both {} and {}",
+                            name,
+                            artists[other].id,
+                            artist.id
+                        );
+                    }
+                }
+            }
+        }
+        Ok(Self { artists, index })
+    }
+
+    pub fn from_toml(s: &str) -> Result<Self> {
+        let file: ArtistFile = toml::from_str(s)?;
+        Self::new(file.artist)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let s = std::fs::read_to_string(path)?;
+        Self::from_toml(&s).map_err(|e| anyhow!("Failed to load {:?}: {}", path, e))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.artists.is_empty()
+    }
+
+    pub fn artists(&self) -> &[Artist] {
+        &self.artists
+    }
+
+    /// Find an artist by its canonical name or an alias.
+    pub fn get(&self, name: &str) -> Option<&Artist> {
+        self.index.get(name).map(|x| &self.artists[*x])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry() {
+        let registry = ArtistRegistry::from_toml(
+            r#"
+[[artist]]
+id = "hoshimachi-suisei"
+name = "星街すいせい"
+aliases = ["Hoshimachi Suisei", "Suisei"]
+external = { musicbrainz = "1f1b1e4a" }
+
+[[artist]]
+id = "jimmythumb-p"
+name = "ジミーサムP"
+"#,
+        )
+        .unwrap();
+        assert_eq!(registry.get("Suisei").unwrap().name, "星街すいせい");
+        assert_eq!(
+            registry.get("星街すいせい").unwrap().external["musicbrainz"],
+            "1f1b1e4a"
+        );
+        assert_eq!(registry.get("ジミーサムP").unwrap().id, "jimmythumb-p");
+        assert!(registry.get("すいせい").is_none());
+
+        assert!(
+            ArtistRegistry::from_toml(
+                r#"
+[[artist]]
+id = "a"
+name = "A"
+
+[[artist]]
+id = "b"
+name = "B"
+aliases = ["A"]
+"#
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::Redirects;
+use crate::Music;
+
+/// Written to the output directory by `build`.
+pub const BUILD_STATE_FILE: &str = ".suimu-build.json";
+
+/// Clips built last time, used to tell a metadata fix from a new clip.
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct BuildState {
+    pub entries: Vec<BuildEntry>,
+    /// Hashes superseded by metadata fixes, kept across builds.
+    #[serde(default)]
+    pub redirects: Redirects,
+}
+
+/// Platform, video ID, page and clip range, with clip bounds as bits.
+type ClipKey<'a> = (&'a str, &'a str, Option<u32>, Option<u32>, Option<u32>);
+
+/// The source and clip range of a built file, along with its hash.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct BuildEntry {
+    pub video_type: String,
+    pub video_id: String,
+    #[serde(default)]
+    pub page: Option<u32>,
+    pub clip_start: Option<f32>,
+    pub clip_end: Option<f32>,
+    pub xxhash: String,
+}
+
+impl BuildEntry {
+    fn new(x: &Music) -> Self {
+        Self {
+            video_type: x.video_type.as_ref().to_string(),
+            video_id: x.video_id.clone(),
+            page: x.page,
+            clip_start: x.clip_start,
+            clip_end: x.clip_end,
+            xxhash: x.xxhash.clone(),
+        }
+    }
+
+    fn key(&self) -> ClipKey<'_> {
+        (
+            &self.video_type,
+            &self.video_id,
+            self.page,
+            self.clip_start.map(f32::to_bits),
+            self.clip_end.map(f32::to_bits),
+        )
+    }
+}
+
+fn clip_key(x: &Music) -> ClipKey<'_> {
+    (
+        x.video_type.as_ref(),
+        &x.video_id,
+        x.page,
+        x.clip_start.map(f32::to_bits),
+        x.clip_end.map(f32::to_bits),
+    )
+}
+
+impl BuildState {
+    pub fn load(path: &Path) -> Result<Self> {
+        let s = std::fs::read_to_string(path)?;
+        serde_json::from_str(&s).map_err(|e| anyhow!("Failed to load {:?}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn new<'a>(musics: impl IntoIterator<Item = &'a Music>) -> Self {
+        Self {
+            entries: musics.into_iter().map(BuildEntry::new).collect(),
+            redirects: Redirects::default(),
+        }
+    }
+
+    /// The previous hash of each music whose source and clip were built
+    /// under another hash that is no longer in use, as (music index, old
+    /// hash).
+    pub fn renamed(&self, musics: &[Music]) -> Vec<(usize, &str)> {
+        let current = musics
+            .iter()
+            .map(|x| x.xxhash.as_str())
+            .collect::<HashSet<_>>();
+        let built = self
+            .entries
+            .iter()
+            .map(|e| e.xxhash.as_str())
+            .collect::<HashSet<_>>();
+        // Unused hashes by clip, the first built one last
+        let mut unused = HashMap::<_, Vec<_>>::new();
+        for e in self.entries.iter().rev() {
+            if !current.contains(e.xxhash.as_str()) {
+                unused.entry(e.key()).or_default().push(e.xxhash.as_str());
+            }
+        }
+        musics
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| !built.contains(x.xxhash.as_str()))
+            .filter_map(|(idx, x)| Some((idx, unused.get_mut(&clip_key(x))?.pop()?)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MaybeMusic;
+
+    fn music(title: &str, clip_start: &str) -> Music {
+        Music::try_from(MaybeMusic {
+            datetime: "2021-06-25T22:30+09:00".to_string(),
+            video_type: "YOUTUBE".to_string(),
+            video_id: "ZfDYRy17CBY".to_string(),
+            clip_start: clip_start.to_string(),
+            status: Some(0),
+            title: title.to_string(),
+            ..MaybeMusic::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_renamed() {
+        let old = [music("Bluerose", "10"), music("Stellar Stellar", "20")];
+        let state = BuildState::new(&old);
+        let musics = [
+            music("Bluerose", "10"),
+            music("Stellar Stellar (Acoustic ver.)", "20"),
+            music("NEXT COLOR PLANET", "30"),
+        ];
+        assert_eq!(state.renamed(&musics), [(1, old[1].xxhash.as_str())]);
+        assert!(BuildState::new(&musics).renamed(&musics).is_empty());
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use crate::{MaybeMusic, Music, Platform};
+
+/// Default threshold (in seconds) under which a gap between two clips of the
+/// same video is most likely a typo of a contiguous cut.
+pub const SUSPICIOUS_GAP: f32 = 1.0;
+
+/// A problem found between two clips of the same video. Indices point into
+/// the slice passed to [`find_clip_issues`].
+#[derive(Debug, PartialEq)]
+pub enum ClipIssue {
+    Overlap(usize, usize),
+    Gap(usize, usize, f32),
+}
+
+/// Find rows sharing the same [`MaybeMusic::hash`]. Each pair is the index of
+/// the first occurrence and the index of the duplicate.
+pub fn find_duplicates<'a>(
+    musics: impl IntoIterator<Item = &'a MaybeMusic>,
+) -> Vec<(usize, usize)> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut ret = vec![];
+    for (idx, x) in musics.into_iter().enumerate() {
+        let hash = x.hash();
+        match seen.get(&hash) {
+            Some(first) => ret.push((*first, idx)),
+            None => {
+                seen.insert(hash, idx);
+            }
+        }
+    }
+    ret
+}
+
+/// Find overlapping clips and gaps shorter than `suspicious_gap` between clips
+/// of the same video. A missing `clip_start` or `clip_end` extends the clip to the
+/// start or end of the video. Exact duplicates are left to
+/// [`find_duplicates`].
+pub fn find_clip_issues(musics: &[Music], suspicious_gap: f32) -> Vec<ClipIssue> {
+    let mut videos: HashMap<(&Platform, &str, Option<u32>), Vec<usize>> = HashMap::new();
+    for (idx, x) in musics.iter().enumerate() {
+        videos
+            .entry((&x.video_type, &x.video_id, x.page))
+            .or_default()
+            .push(idx);
+    }
+
+    let range = |idx: usize| {
+        let x = &musics[idx];
+        (
+            x.clip_start.unwrap_or(0.0),
+            x.clip_end.unwrap_or(f32::INFINITY),
+        )
+    };
+
+    let mut ret = vec![];
+    for mut clips in videos.into_values() {
+        clips.sort_by(|a, b| {
+            let (a, b) = (range(*a), range(*b));
+            a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1))
+        });
+        for (i, &one) in clips.iter().enumerate() {
+            let (_, one_end) = range(one);
+            for &two in clips.iter().skip(i + 1) {
+                let (two_start, _) = range(two);
+                if musics[one].xxhash == musics[two].xxhash {
+                    continue;
+                }
+                if two_start < one_end {
+                    ret.push(ClipIssue::Overlap(one, two));
+                }
+            }
+            if let Some(&next) = clips.get(i + 1) {
+                let gap = range(next).0 - one_end;
+                if gap > 0.0 && gap < suspicious_gap {
+                    ret.push(ClipIssue::Gap(one, next, gap));
+                }
+            }
+        }
+    }
+    ret.sort_by_key(|x| match x {
+        ClipIssue::Overlap(a, b) | ClipIssue::Gap(a, b, _) => (*a, *b),
+    });
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+
+    use super::*;
+
+    fn clip(video_id: &str, title: &str, start: Option<f32>, end: Option<f32>) -> Music {
+        Music {
+            datetime: DateTime::parse_from_rfc3339("2021-06-25T22:30:00+09:00").unwrap(),
+            video_type: Platform::YouTube,
+            video_id: video_id.to_string(),
+            page: None,
+            clip_start: start,
+            clip_end: end,
+            xxhash: format!("{}{}", video_id, title),
+            status: 0,
+            title: title.to_string(),
+            base_title: title.to_string(),
+            variant: None,
+            artist: "".to_string(),
+            performer: "".to_string(),
+            artists: vec![],
+            performers: vec![],
+            comment: "".to_string(),
+            tags: Default::default(),
+            extra: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        let one = MaybeMusic {
+            video_type: "YOUTUBE".to_string(),
+            video_id: "ZfDYRy17CBY".to_string(),
+            title: "Bluerose".to_string(),
+            ..Default::default()
+        };
+        let two = MaybeMusic {
+            title: "GHOST".to_string(),
+            ..one.clone()
+        };
+        assert_eq!(
+            find_duplicates(&[one.clone(), two.clone(), one.clone(), two]),
+            vec![(0, 2), (1, 3)]
+        );
+        assert!(find_duplicates(&[one]).is_empty());
+    }
+
+    #[test]
+    fn test_find_clip_issues() {
+        assert_eq!(
+            find_clip_issues(
+                &[
+                    clip("a", "1", Some(10.0), Some(20.0)),
+                    clip("a", "2", Some(15.0), Some(30.0)),
+                    clip("a", "3", Some(30.5), None),
+                    clip("b", "1", Some(15.0), Some(30.0)),
+                ],
+                SUSPICIOUS_GAP
+            ),
+            vec![ClipIssue::Overlap(0, 1), ClipIssue::Gap(1, 2, 0.5)]
+        );
+
+        // Contiguous and whole-video clips
+        assert!(
+            find_clip_issues(
+                &[
+                    clip("a", "1", Some(10.0), Some(20.0)),
+                    clip("a", "2", Some(20.0), Some(30.0)),
+                    clip("b", "1", None, None),
+                ],
+                SUSPICIOUS_GAP
+            )
+            .is_empty()
+        );
+
+        assert_eq!(
+            find_clip_issues(
+                &[
+                    clip("a", "1", None, None),
+                    clip("a", "2", Some(20.0), Some(30.0)),
+                ],
+                SUSPICIOUS_GAP
+            ),
+            vec![ClipIssue::Overlap(0, 1)]
+        );
+    }
+}
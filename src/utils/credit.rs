@@ -0,0 +1,80 @@
+use serde::Deserialize;
+
+/// Splits credit fields like `artist` and `performer` into individual names.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CreditParser {
+    /// Separators between names, matched in order.
+    pub separators: Vec<String>,
+    /// Names containing a separator that must not be split.
+    pub escapes: Vec<String>,
+}
+
+impl Default for CreditParser {
+    fn default() -> Self {
+        Self {
+            separators: [" feat. ", " ft. ", "×", " x ", "、", ",", "&"]
+                .iter()
+                .map(|x| x.to_string())
+                .collect(),
+            escapes: vec![],
+        }
+    }
+}
+
+impl CreditParser {
+    /// Split a credit into trimmed, non-empty names, in order.
+    pub fn split(&self, credit: &str) -> Vec<String> {
+        let mut ret = vec![];
+        let mut cur = String::new();
+        let mut rest = credit;
+        'outer: while !rest.is_empty() {
+            for escape in self.escapes.iter().filter(|x| !x.is_empty()) {
+                if rest.starts_with(escape.as_str()) {
+                    cur.push_str(escape);
+                    rest = &rest[escape.len()..];
+                    continue 'outer;
+                }
+            }
+            for sep in self.separators.iter().filter(|x| !x.is_empty()) {
+                if rest.starts_with(sep.as_str()) {
+                    ret.push(std::mem::take(&mut cur));
+                    rest = &rest[sep.len()..];
+                    continue 'outer;
+                }
+            }
+            let ch = rest.chars().next().unwrap();
+            cur.push(ch);
+            rest = &rest[ch.len_utf8()..];
+        }
+        ret.push(cur);
+        ret.into_iter()
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty())
+            .collect()
+    }
+}
+
+#[test]
+fn test_split() {
+    let parser = CreditParser::default();
+    assert_eq!(parser.split("星街すいせい"), ["星街すいせい"]);
+    assert_eq!(parser.split(""), Vec::<String>::new());
+    assert_eq!(
+        parser.split("星街すいせい × 大神ミオ, 白上フブキ"),
+        ["星街すいせい", "大神ミオ", "白上フブキ"]
+    );
+    assert_eq!(
+        parser.split("TAKU INOUE feat. 星街すいせい"),
+        ["TAKU INOUE", "星街すいせい"]
+    );
+
+    let parser = CreditParser {
+        escapes: vec!["Tom & Jerry".to_string()],
+        ..CreditParser::default()
+    };
+    assert_eq!(
+        parser.split("Tom & Jerry & Spike"),
+        ["Tom & Jerry", "Spike"]
+    );
+}
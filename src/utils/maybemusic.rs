@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::fmt::{Display, Formatter, Result as FmtResult};
 use std::hash::Hasher;
 
@@ -5,6 +6,26 @@ use chrono::Local;
 use serde::{Deserialize, Serialize};
 use twox_hash::XxHash64;
 
+/// Columns read into [`MaybeMusic`], in their usual order.
+pub const COLUMNS: &[&str] = &[
+    "datetime",
+    "video_type",
+    "video_id",
+    "clip_start",
+    "clip_end",
+    "status",
+    "title",
+    "artist",
+    "performer",
+    "comment",
+];
+
+/// Columns read into [`MaybeMusic`] which may be left out.
+pub const OPTIONAL_COLUMNS: &[&str] = &["tags"];
+
+/// Separator of tags in the `tags` column.
+pub const TAG_SEPARATOR: char = ';';
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct MaybeMusic {
     pub datetime: String,
@@ -17,6 +38,12 @@ pub struct MaybeMusic {
     pub artist: String,
     pub performer: String,
     pub comment: String,
+    /// Tags separated by [`TAG_SEPARATOR`], e.g. `3D live;collab`.
+    #[serde(default)]
+    pub tags: String,
+    /// Columns other than [`COLUMNS`] and [`OPTIONAL_COLUMNS`], by header.
+    #[serde(skip)]
+    pub extra: BTreeMap<String, String>,
 }
 
 impl Display for MaybeMusic {
@@ -49,6 +76,8 @@ impl Default for MaybeMusic {
             artist: String::new(),
             performer: String::new(),
             comment: String::new(),
+            tags: String::new(),
+            extra: BTreeMap::new(),
         }
     }
 }
@@ -58,16 +87,52 @@ impl MaybeMusic {
         // https://github.com/suisei-cn/suisei-music/blob/6b5767b58eee61cf2bcdf2be60ac9e06c773809d/tools/mod.py#L28
         let mut hasher = XxHash64::with_seed(0x9f88f860);
 
-        hasher.write(self.video_type.as_bytes());
-        hasher.write(self.video_id.as_bytes());
-        hasher.write(self.clip_start.as_bytes());
-        hasher.write(self.clip_end.as_bytes());
-        hasher.write(self.title.as_bytes());
-        hasher.write(self.artist.as_bytes());
-        hasher.write(self.performer.as_bytes());
+        for field in self.hashed_fields() {
+            hasher.write(field.as_bytes());
+        }
 
         format!("{:016x}", hasher.finish())
     }
+
+    /// Value of a column, either one of [`COLUMNS`] or an extra one.
+    pub fn get(&self, column: &str) -> Option<String> {
+        Some(match column {
+            "datetime" => self.datetime.clone(),
+            "video_type" => self.video_type.clone(),
+            "video_id" => self.video_id.clone(),
+            "clip_start" => self.clip_start.clone(),
+            "clip_end" => self.clip_end.clone(),
+            "status" => self.status.map(|x| x.to_string()).unwrap_or_default(),
+            "title" => self.title.clone(),
+            "artist" => self.artist.clone(),
+            "performer" => self.performer.clone(),
+            "comment" => self.comment.clone(),
+            "tags" => self.tags.clone(),
+            _ => return self.extra.get(column).cloned(),
+        })
+    }
+
+    /// Non-empty tags of the `tags` column, trimmed.
+    pub fn tags(&self) -> Vec<&str> {
+        self.tags
+            .split(TAG_SEPARATOR)
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .collect()
+    }
+
+    /// Fields covered by [`MaybeMusic::hash`], in order.
+    pub fn hashed_fields(&self) -> [&str; 7] {
+        [
+            &self.video_type,
+            &self.video_id,
+            &self.clip_start,
+            &self.clip_end,
+            &self.title,
+            &self.artist,
+            &self.performer,
+        ]
+    }
 }
 
 #[cfg(test)]
@@ -88,6 +153,8 @@ mod tests {
                 artist: "星街すいせい".to_string(),
                 performer: "星街すいせい".to_string(),
                 comment: "".to_string(),
+                tags: String::new(),
+                extra: BTreeMap::new(),
             })
             .hash(),
             "0c2b9da9cfe08c9e"
@@ -105,6 +172,8 @@ mod tests {
                 artist: "星街すいせい".to_string(),
                 performer: "星街すいせい".to_string(),
                 comment: "".to_string(),
+                tags: String::new(),
+                extra: BTreeMap::new(),
             })
             .hash(),
             "4db7f3845af9cce9"
@@ -122,6 +191,8 @@ mod tests {
                 artist: "極悪P".to_string(),
                 performer: "星街すいせい".to_string(),
                 comment: "".to_string(),
+                tags: String::new(),
+                extra: BTreeMap::new(),
             })
             .hash(),
             "d52a8a351014118c"
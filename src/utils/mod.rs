@@ -1,31 +1,40 @@
+mod artist;
+mod build_state;
+mod clip;
+mod credit;
 mod interactive;
 mod maybemusic;
 mod music;
+mod platform;
 mod process_music;
+mod reader;
+mod redirect;
 pub mod rfc3339;
+mod variant;
+mod video_id;
 
-use std::io::Read;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::{Read, Write};
 
-use anyhow::{anyhow, ensure, Result};
-use csv::{Error, Reader};
+use anyhow::Result;
+pub use artist::{Artist, ArtistRegistry, ARTISTS_FILE};
+pub use build_state::{BuildEntry, BuildState, BUILD_STATE_FILE};
+pub use clip::{find_clip_issues, find_duplicates, ClipIssue, SUSPICIOUS_GAP};
+pub use credit::CreditParser;
+use csv::Writer;
 pub use interactive::*;
-pub use maybemusic::MaybeMusic;
-pub use music::Music;
-pub use process_music::{process_music, EnvConf, PLATFORM_INFO};
-use serde::Serialize;
+pub use maybemusic::{MaybeMusic, COLUMNS, OPTIONAL_COLUMNS, TAG_SEPARATOR};
+pub use music::{parse_time, Music};
+pub use platform::{PlatformOverride, PlatformRegistry, PlatformSettings};
+pub use process_music::{process_music, retag_music, EnvConf};
+pub use reader::{check_headers, RowError, Rows};
+pub use redirect::Redirects;
+use serde::{Serialize, Serializer};
 use strum_macros;
+pub use variant::VariantParser;
+pub use video_id::{av_to_bv, bv_to_av, snowflake_time};
 
-#[derive(
-    Clone,
-    Copy,
-    Debug,
-    Eq,
-    Hash,
-    PartialEq,
-    Serialize,
-    strum_macros::AsRefStr,
-    strum_macros::EnumString,
-)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq, strum_macros::EnumString)]
 pub enum Platform {
     #[strum(serialize = "TWITTER")]
     Twitter,
@@ -33,23 +42,107 @@ pub enum Platform {
     Bilibili,
     #[strum(serialize = "YOUTUBE")]
     YouTube,
+    #[strum(serialize = "NICONICO")]
+    Niconico,
+    #[strum(serialize = "TWITCH")]
+    Twitch,
+    #[strum(serialize = "SOUNDCLOUD")]
+    SoundCloud,
+    /// A file under the archive directory, e.g. a paid concert.
+    #[strum(serialize = "LOCAL")]
+    Local,
+    /// A platform defined in the config.
+    #[strum(disabled)]
+    Other(String),
+}
+
+impl AsRef<str> for Platform {
+    fn as_ref(&self) -> &str {
+        match self {
+            Platform::Twitter => "TWITTER",
+            Platform::Bilibili => "BILIBILI",
+            Platform::YouTube => "YOUTUBE",
+            Platform::Niconico => "NICONICO",
+            Platform::Twitch => "TWITCH",
+            Platform::SoundCloud => "SOUNDCLOUD",
+            Platform::Local => "LOCAL",
+            Platform::Other(name) => name,
+        }
+    }
+}
+
+impl Serialize for Platform {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            Platform::Other(name) => serializer.serialize_str(name),
+            // Variant names, as derived before configured platforms
+            _ => serializer.serialize_str(&format!("{:?}", self)),
+        }
+    }
+}
+
+/// A CSV record along with the line it starts on.
+#[derive(Clone, Debug)]
+pub struct Row {
+    pub line: u64,
+    pub music: MaybeMusic,
 }
 
 pub fn check_csv(source: impl Read) -> Result<Vec<MaybeMusic>> {
-    let mut reader = Reader::from_reader(source);
-    reader
-        .deserialize()
-        .collect::<Result<Vec<MaybeMusic>, Error>>()
-        .map_err(|err| anyhow!(err))
+    Ok(check_csv_rows(source)?
+        .into_iter()
+        .map(|row| row.music)
+        .collect())
+}
+
+/// Every row of `source`, failing on the first malformed one. See [`Rows`]
+/// to read past them.
+pub fn check_csv_rows(source: impl Read) -> Result<Vec<Row>> {
+    Ok(Rows::new(source)?.collect::<Result<_, _>>()?)
+}
+
+/// Write rows under `headers`, which may include extra columns.
+pub fn write_csv_rows<'a>(
+    writer: impl Write,
+    headers: &[String],
+    rows: impl IntoIterator<Item = &'a Row>,
+) -> Result<()> {
+    let mut writer = Writer::from_writer(writer);
+    writer.write_record(headers)?;
+    for row in rows {
+        writer.write_record(headers.iter().map(|x| row.music.get(x).unwrap_or_default()))?;
+    }
+    writer.flush()?;
+    Ok(())
 }
 
-pub fn check_logic(x: &Music) -> Result<()> {
+/// Inconsistency found by [`check_logic`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LogicError {
+    ClipOrder,
+    ZeroLengthClip,
+}
+
+impl Display for LogicError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            LogicError::ClipOrder => write!(f, "clip_start is later than clip_end"),
+            LogicError::ZeroLengthClip => write!(f, "Zero-length clip"),
+        }
+    }
+}
+
+impl std::error::Error for LogicError {}
+
+pub fn check_logic(x: &Music) -> Result<(), LogicError> {
     // If clip start & end presents, make sure it's consistent
-    if x.clip_start.is_some() && x.clip_end.is_some() {
-        ensure!(
-            (x.clip_start.unwrap() < x.clip_end.unwrap()),
-            "clip_start is later than clip_end"
-        )
+    if let (Some(clip_start), Some(clip_end)) = (x.clip_start, x.clip_end) {
+        if clip_start > clip_end {
+            return Err(LogicError::ClipOrder);
+        }
+        if clip_start == clip_end {
+            return Err(LogicError::ZeroLengthClip);
+        }
     }
 
     Ok(())
@@ -72,6 +165,16 @@ mod tests {
         assert!(ret1.is_ok());
         assert_eq!(ret1.unwrap().len(), 1);
 
+        let ret2 = check_csv_rows(
+            "datetime,video_type,video_id,clip_start,clip_end,status,title,artist,performer,comment
+2018-03-27T20:54+09:00,TWITTER,978601113791299585,,,0,Starduster,ジミーサムP,星街すいせい,\"multi
+line\"
+2018-03-27T20:54+09:00,TWITTER,978601113791299585,,,0,Starduster,ジミーサムP,星街すいせい,"
+                .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(ret2.iter().map(|x| x.line).collect::<Vec<_>>(), [2, 4]);
+
         assert!(
             check_csv(
                 "video_type,video_id,clip_start,clip_end,status,title,artist,performer,comment
@@ -91,13 +194,20 @@ TWITTER,978601113791299585,,,0,Starduster,ジミーサムP,星街すいせい,"
                 datetime: common_dt,
                 video_type: Platform::YouTube,
                 video_id: "ZfDYRy17CBY".to_string(),
+                page: None,
                 clip_end: None,
                 xxhash: "".to_string(),
                 status: 0,
                 title: "".to_string(),
+                base_title: "".to_string(),
+                variant: None,
                 artist: "".to_string(),
                 performer: "".to_string(),
+                artists: vec![],
+                performers: vec![],
                 comment: "".to_string(),
+                tags: Default::default(),
+                extra: Default::default(),
 
                 clip_start: Some(1.1),
             })
@@ -109,12 +219,19 @@ TWITTER,978601113791299585,,,0,Starduster,ジミーサムP,星街すいせい,"
                 datetime: common_dt,
                 video_type: Platform::YouTube,
                 video_id: "ZfDYRy17CBY".to_string(),
+                page: None,
                 xxhash: "".to_string(),
                 status: 0,
                 title: "".to_string(),
+                base_title: "".to_string(),
+                variant: None,
                 artist: "".to_string(),
                 performer: "".to_string(),
+                artists: vec![],
+                performers: vec![],
                 comment: "".to_string(),
+                tags: Default::default(),
+                extra: Default::default(),
 
                 clip_start: Some(3.1),
                 clip_end: Some(2.2),
@@ -127,17 +244,49 @@ TWITTER,978601113791299585,,,0,Starduster,ジミーサムP,星街すいせい,"
                 datetime: common_dt,
                 video_type: Platform::YouTube,
                 video_id: "ZfDYRy17CBY".to_string(),
+                page: None,
                 xxhash: "".to_string(),
                 status: 0,
                 title: "".to_string(),
+                base_title: "".to_string(),
+                variant: None,
                 artist: "".to_string(),
                 performer: "".to_string(),
+                artists: vec![],
+                performers: vec![],
                 comment: "".to_string(),
+                tags: Default::default(),
+                extra: Default::default(),
 
                 clip_start: Some(1.1),
                 clip_end: Some(2.2),
             })
             .is_ok()
         );
+
+        assert!(
+            check_logic(&Music {
+                datetime: common_dt,
+                video_type: Platform::YouTube,
+                video_id: "ZfDYRy17CBY".to_string(),
+                page: None,
+                xxhash: "".to_string(),
+                status: 0,
+                title: "".to_string(),
+                base_title: "".to_string(),
+                variant: None,
+                artist: "".to_string(),
+                performer: "".to_string(),
+                artists: vec![],
+                performers: vec![],
+                comment: "".to_string(),
+                tags: Default::default(),
+                extra: Default::default(),
+
+                clip_start: Some(2.2),
+                clip_end: Some(2.2),
+            })
+            .is_err()
+        );
     }
 }
@@ -1,26 +1,44 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::convert::TryFrom;
 use std::fmt::{Display, Formatter, Result as FmtResult};
-use std::str::FromStr;
 
 use anyhow::{anyhow, bail, Result};
 use chrono::{DateTime, FixedOffset};
 use serde::{Serialize, Serializer};
 
+use crate::config::Config;
+use crate::utils::PlatformRegistry;
 use crate::{MaybeMusic, Platform};
+
 #[derive(Debug, Serialize)]
 pub struct Music {
     #[serde(serialize_with = "serialize_3339")]
     pub datetime: DateTime<FixedOffset>,
     pub video_type: Platform,
     pub video_id: String,
+    /// Part of a multi-part video, e.g. `p=3` on Bilibili.
+    pub page: Option<u32>,
     pub clip_start: Option<f32>,
     pub clip_end: Option<f32>,
     pub xxhash: String,
     pub status: u16,
     pub title: String,
+    /// `title` without its variant suffix, if the variant is known.
+    pub base_title: String,
+    /// Known variant label parsed from the title suffix, e.g. `Acoustic ver.`.
+    pub variant: Option<String>,
     pub artist: String,
     pub performer: String,
+    /// `artist` split into individual names.
+    pub artists: Vec<String>,
+    /// `performer` split into individual names.
+    pub performers: Vec<String>,
     pub comment: String,
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+    /// Columns other than the known ones, by header.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra: BTreeMap<String, String>,
 }
 
 pub fn serialize_3339<S>(date: &DateTime<FixedOffset>, serializer: S) -> Result<S::Ok, S::Error>
@@ -31,24 +49,58 @@ where
     serializer.serialize_str(&s)
 }
 
-fn parse_time(time: &str) -> Result<DateTime<FixedOffset>> {
+/// Parse an RFC 3339 datetime, with or without seconds.
+pub fn parse_time(time: &str) -> Result<DateTime<FixedOffset>> {
     DateTime::parse_from_rfc3339(time)
         .or_else(|_| DateTime::parse_from_str(time, "%Y-%m-%dT%H:%M%z"))
         .or_else(|_| bail!("Error parsing time"))
 }
 
+/// Parse a clip bound in seconds, if any. `NaN` and infinities are rejected.
+fn parse_clip(value: &str, field: &str) -> Result<Option<f32>> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    let ret = value.parse::<f32>()?;
+    if !ret.is_finite() {
+        bail!("{} is not a finite number", field);
+    }
+    Ok(Some(ret))
+}
+
 impl TryFrom<MaybeMusic> for Music {
     type Error = anyhow::Error;
 
     fn try_from(v: MaybeMusic) -> Result<Music> {
+        Music::convert(v, &Config::default())
+    }
+}
+
+impl Music {
+    /// Convert a row, splitting credits and title variants as configured.
+    pub fn convert(v: MaybeMusic, config: &Config) -> Result<Music> {
         let status = v.status.ok_or_else(|| anyhow!("No status present"))?;
         let datetime = parse_time(&v.datetime)?;
-        let video_type = Platform::from_str(v.video_type.trim())
-            .map_err(|_| anyhow!("Platform not supported"))?;
-        let video_id = v.video_id.trim().to_string();
+        let video_type = config
+            .platforms
+            .parse(v.video_type.trim())
+            .ok_or_else(|| anyhow!("Platform not supported"))?;
+        let video_id = v.video_id.trim();
         if video_id.is_empty() {
             return Err(anyhow!("video_id is empty"));
         }
+        // Other forms of an ID, like `av` numbers, are left to the
+        // `invalid-video-id` rule, and used as they are if they can't be fixed.
+        let video_id = match video_type.extract_id(video_id) {
+            Some(id) => id,
+            // Outside of the archive directory
+            None if video_type == Platform::Local => return Err(anyhow!("Invalid video_id")),
+            None => video_id.to_string(),
+        };
+        let (video_id, page) = match video_type.split_page(&video_id) {
+            Some((id, page)) => (id.to_string(), page),
+            None => (video_id, None),
+        };
 
         let title = v.title.trim();
 
@@ -56,29 +108,37 @@ impl TryFrom<MaybeMusic> for Music {
             return Err(anyhow!("Title is empty"));
         }
 
-        let parsed_clip_start = if v.clip_start.is_empty() {
-            None
-        } else {
-            Some(v.clip_start.parse::<f32>()?)
-        };
+        let parsed_clip_start = parse_clip(&v.clip_start, "clip_start")?;
+        let parsed_clip_end = parse_clip(&v.clip_end, "clip_end")?;
 
-        let parsed_clip_end = if v.clip_start.is_empty() {
-            None
-        } else {
-            Some(v.clip_end.parse::<f32>()?)
+        // Unknown labels stay in the title, for `unknown-variant` to report.
+        let (base_title, variant) = match config.variants.split(title) {
+            (base_title, Some(label)) if config.variants.is_known(label) => {
+                (base_title, Some(label))
+            }
+            _ => (title, None),
         };
-
         let xxhash = v.hash();
+        let artist = v.artist.trim().to_string();
+        let performer = v.performer.trim().to_string();
+        let tags = v.tags().into_iter().map(str::to_string).collect();
 
         Ok(Music {
             datetime,
             status,
             video_type,
             video_id,
+            page,
             title: title.to_string(),
-            artist: v.artist.trim().to_string(),
-            performer: v.performer.trim().to_string(),
+            base_title: base_title.to_string(),
+            variant: variant.map(str::to_string),
+            artists: config.credits.split(&artist),
+            performers: config.credits.split(&performer),
+            artist,
+            performer,
             comment: v.comment,
+            tags,
+            extra: v.extra,
             xxhash,
             clip_start: parsed_clip_start,
             clip_end: parsed_clip_end,
@@ -103,4 +163,111 @@ impl Music {
     pub fn is_member_only(&self) -> bool {
         self.status & 8 > 0
     }
+
+    /// URL of the source video. Local files have none.
+    pub fn source_url(&self, platforms: &PlatformRegistry) -> Option<String> {
+        let info = platforms.get(&self.video_type)?;
+        let url = info.url_template.replace("{}", &self.video_id);
+        Some(match self.page {
+            Some(page) => format!("{}?p={}", url, page),
+            None => url,
+        })
+    }
+
+    /// File name of the downloaded source video, or the path of a local file
+    /// under the archive directory.
+    pub fn source_file(&self, platforms: &PlatformRegistry) -> String {
+        let info = match platforms.get(&self.video_type) {
+            Some(info) => info,
+            None => return self.video_id.clone(),
+        };
+        // SoundCloud IDs are paths. Escaping `%` keeps names distinct, and
+        // leaves a bare `%` free to mark the page.
+        let id = self.video_id.replace('%', "%25").replace('/', "%2F");
+        match self.page {
+            Some(page) => format!("{}%p{}.{}", id, page, info.source_ext),
+            None => format!("{}.{}", id, info.source_ext),
+        }
+    }
+}
+
+#[test]
+fn test_page() {
+    let v = MaybeMusic {
+        datetime: "2020-01-31T19:58+09:00".to_string(),
+        video_type: "BILIBILI".to_string(),
+        video_id: "BV1U7411s7X1?p=2".to_string(),
+        status: Some(0),
+        title: "ホワイトハッピー".to_string(),
+        ..MaybeMusic::default()
+    };
+    let music = Music::try_from(v).unwrap();
+    let platforms = PlatformRegistry::default();
+    assert_eq!(music.video_id, "BV1U7411s7X1");
+    assert_eq!(music.page, Some(2));
+    assert_eq!(
+        music.source_url(&platforms).as_deref(),
+        Some("https://www.bilibili.com/video/BV1U7411s7X1?p=2")
+    );
+    assert_eq!(music.source_file(&platforms), "BV1U7411s7X1%p2.flv");
+}
+
+#[test]
+fn test_clip_not_finite() {
+    let v = MaybeMusic {
+        datetime: "2021-06-25T22:30+09:00".to_string(),
+        video_type: "YOUTUBE".to_string(),
+        video_id: "ZfDYRy17CBY".to_string(),
+        clip_start: "NaN".to_string(),
+        status: Some(0),
+        title: "Bluerose".to_string(),
+        ..MaybeMusic::default()
+    };
+    assert_eq!(
+        Music::try_from(v).unwrap_err().to_string(),
+        "clip_start is not a finite number"
+    );
+}
+
+#[test]
+fn test_local() {
+    let v = MaybeMusic {
+        datetime: "2021-03-22T18:00+09:00".to_string(),
+        video_type: "LOCAL".to_string(),
+        video_id: "live/2021-first-live.mkv".to_string(),
+        status: Some(0),
+        title: "Stellar Stellar".to_string(),
+        ..MaybeMusic::default()
+    };
+    let music = Music::try_from(v).unwrap();
+    let platforms = PlatformRegistry::default();
+    assert_eq!(music.source_url(&platforms), None);
+    assert_eq!(music.source_file(&platforms), "live/2021-first-live.mkv");
+    let v = MaybeMusic {
+        datetime: "2021-03-22T18:00+09:00".to_string(),
+        video_type: "LOCAL".to_string(),
+        video_id: "../2021-first-live.mkv".to_string(),
+        status: Some(0),
+        title: "Stellar Stellar".to_string(),
+        ..MaybeMusic::default()
+    };
+    assert!(Music::try_from(v).is_err());
+}
+
+#[test]
+fn test_source_file() {
+    let platforms = PlatformRegistry::default();
+    let file = |id: &str| {
+        let v = MaybeMusic {
+            datetime: "2021-06-25T22:30+09:00".to_string(),
+            video_type: "SOUNDCLOUD".to_string(),
+            video_id: id.to_string(),
+            status: Some(0),
+            title: "Bluerose".to_string(),
+            ..MaybeMusic::default()
+        };
+        Music::try_from(v).unwrap().source_file(&platforms)
+    };
+    assert_ne!(file("a_b/c"), file("a/b_c"));
+    assert!(file("hoshimachi-suisei/bluerose").starts_with("hoshimachi-suisei%2Fbluerose."));
 }
@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::Platform;
+
+/// How sources of a platform are downloaded.
+#[derive(Clone, Debug)]
+pub struct PlatformSettings {
+    /// Source URL, with `{}` replaced by the video ID.
+    pub url_template: String,
+    /// youtube-dl format selector.
+    pub format: String,
+    pub source_ext: String,
+    /// Pattern video IDs must match, on top of the built-in validation.
+    pub id_regex: Option<Regex>,
+    /// Extra arguments passed to youtube-dl.
+    pub downloader_args: Vec<String>,
+}
+
+impl PlatformSettings {
+    fn new(url_template: &str, format: &str, source_ext: &str) -> Self {
+        Self {
+            url_template: url_template.to_string(),
+            format: format.to_string(),
+            source_ext: source_ext.to_string(),
+            id_regex: None,
+            downloader_args: vec![],
+        }
+    }
+}
+
+/// A platform as written in `[platforms.NAME]` of the config. Fields left out
+/// keep their built-in value.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlatformOverride {
+    pub url_template: Option<String>,
+    pub format: Option<String>,
+    pub source_ext: Option<String>,
+    pub id_pattern: Option<String>,
+    pub downloader_args: Option<Vec<String>>,
+}
+
+/// Platforms rows may refer to, by name.
+#[derive(Clone, Debug)]
+pub struct PlatformRegistry {
+    platforms: HashMap<String, PlatformSettings>,
+}
+
+impl Default for PlatformRegistry {
+    fn default() -> Self {
+        let mut m = HashMap::new();
+        m.insert(
+            Platform::YouTube,
+            PlatformSettings::new(
+                "https://www.youtube.com/watch?v={}",
+                "bestaudio[ext=m4a]",
+                "mp4",
+            ),
+        );
+        m.insert(
+            Platform::Twitter,
+            PlatformSettings::new(
+                "https://www.twitter.com/i/status/{}",
+                "best[ext=mp4]",
+                "mp4",
+            ),
+        );
+        m.insert(
+            Platform::Bilibili,
+            PlatformSettings::new("https://www.bilibili.com/video/{}", "best[ext=flv]", "flv"),
+        );
+        m.insert(
+            Platform::Niconico,
+            PlatformSettings::new("https://www.nicovideo.jp/watch/{}", "best[ext=mp4]", "mp4"),
+        );
+        m.insert(
+            Platform::Twitch,
+            PlatformSettings::new(
+                "https://www.twitch.tv/videos/{}",
+                "Audio_Only/best[ext=mp4]",
+                "mp4",
+            ),
+        );
+        m.insert(
+            Platform::SoundCloud,
+            PlatformSettings::new("https://soundcloud.com/{}", "bestaudio[ext=mp3]", "mp3"),
+        );
+        Self {
+            platforms: m
+                .into_iter()
+                .map(|(k, v)| (k.as_ref().to_string(), v))
+                .collect(),
+        }
+    }
+}
+
+impl PlatformRegistry {
+    /// The built-in platforms, overridden and extended by `overrides`.
+    pub fn with_overrides(overrides: &HashMap<String, PlatformOverride>) -> Result<Self> {
+        let mut ret = Self::default();
+        for (name, x) in overrides {
+            if Platform::from_str(name) == Ok(Platform::Local) {
+                bail!("Platform {} can't be configured", name);
+            }
+            let mut settings = match ret.platforms.remove(name) {
+                Some(settings) => settings,
+                None => PlatformSettings::new(
+                    x.url_template
+                        .as_deref()
+                        .ok_or_else(|| anyhow!("Platform {} has no url_template", name))?,
+                    "best",
+                    x.source_ext
+                        .as_deref()
+                        .ok_or_else(|| anyhow!("Platform {} has no source_ext", name))?,
+                ),
+            };
+            if let Some(url_template) = &x.url_template {
+                settings.url_template = url_template.clone();
+            }
+            if let Some(format) = &x.format {
+                settings.format = format.clone();
+            }
+            if let Some(source_ext) = &x.source_ext {
+                settings.source_ext = source_ext.clone();
+            }
+            if let Some(pattern) = &x.id_pattern {
+                settings.id_regex = Some(
+                    Regex::new(pattern)
+                        .map_err(|e| anyhow!("Invalid id_pattern of {}: {}", name, e))?,
+                );
+            }
+            if let Some(args) = &x.downloader_args {
+                settings.downloader_args = args.clone();
+            }
+            ret.platforms.insert(name.clone(), settings);
+        }
+        Ok(ret)
+    }
+
+    /// Parse a platform name, if it's built in or configured.
+    pub fn parse(&self, name: &str) -> Option<Platform> {
+        let platform = Platform::from_str(name).unwrap_or_else(|_| Platform::Other(name.into()));
+        if platform == Platform::Local || self.platforms.contains_key(name) {
+            Some(platform)
+        } else {
+            None
+        }
+    }
+
+    /// Settings of a platform. Local files have none.
+    pub fn get(&self, platform: &Platform) -> Option<&PlatformSettings> {
+        self.platforms.get(platform.as_ref())
+    }
+
+    /// Whether `id` is a valid video ID, by both the built-in validation and
+    /// the configured pattern.
+    pub fn is_valid_id(&self, platform: &Platform, id: &str) -> bool {
+        platform.is_valid_id(id)
+            && self
+                .get(platform)
+                .and_then(|x| x.id_regex.as_ref())
+                .is_none_or(|re| re.is_match(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry() {
+        let overrides: HashMap<String, PlatformOverride> = toml::from_str(
+            r#"
+[TWITTER]
+url_template = "https://x.com/i/status/{}"
+
+[VIMEO]
+url_template = "https://vimeo.com/{}"
+source_ext = "mp4"
+id_pattern = '^\d+$'
+downloader_args = ["--referer", "https://vimeo.com"]
+"#,
+        )
+        .unwrap();
+        let registry = PlatformRegistry::with_overrides(&overrides).unwrap();
+        let twitter = registry.get(&Platform::Twitter).unwrap();
+        assert_eq!(twitter.url_template, "https://x.com/i/status/{}");
+        assert_eq!(twitter.format, "best[ext=mp4]");
+
+        let vimeo = registry.parse("VIMEO").unwrap();
+        assert_eq!(vimeo, Platform::Other("VIMEO".to_string()));
+        assert_eq!(vimeo.as_ref(), "VIMEO");
+        assert_eq!(registry.get(&vimeo).unwrap().downloader_args.len(), 2);
+        assert!(registry.is_valid_id(&vimeo, "76979871"));
+        assert!(!registry.is_valid_id(&vimeo, "abc"));
+
+        assert_eq!(registry.parse("LOCAL"), Some(Platform::Local));
+        assert_eq!(PlatformRegistry::default().parse("VIMEO"), None);
+
+        let overrides: HashMap<String, PlatformOverride> =
+            toml::from_str("[VIMEO]\nsource_ext = \"mp4\"").unwrap();
+        assert!(PlatformRegistry::with_overrides(&overrides).is_err());
+    }
+}
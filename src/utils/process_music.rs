@@ -1,59 +1,22 @@
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
-use lazy_static::lazy_static;
 use log::{debug, info, warn};
 
-use crate::{GlobalStat, Music, Platform};
-
-pub struct PlatformSettings {
-    pub url_template: &'static str,
-    format: &'static str,
-    source_ext: &'static str,
-}
-
-lazy_static! {
-    pub static ref PLATFORM_INFO: HashMap<Platform, PlatformSettings> = {
-        let mut m = HashMap::new();
-        m.insert(
-            Platform::YouTube,
-            PlatformSettings {
-                url_template: "https://www.youtube.com/watch?v={}",
-                format: "bestaudio[ext=m4a]",
-                source_ext: "mp4",
-            },
-        );
-        m.insert(
-            Platform::Twitter,
-            PlatformSettings {
-                url_template: "https://www.twitter.com/i/status/{}",
-                format: "best[ext=mp4]",
-                source_ext: "mp4",
-            },
-        );
-        m.insert(
-            Platform::Bilibili,
-            PlatformSettings {
-                url_template: "https://www.bilibili.com/video/{}",
-                format: "best[ext=flv]",
-                source_ext: "flv",
-            },
-        );
-        m
-    };
-}
+use crate::utils::PlatformRegistry;
+use crate::{GlobalStat, Music};
 
 pub struct EnvConf {
     pub source_dir: PathBuf,
+    /// Directory `LOCAL` sources are relative to.
+    pub archive_dir: PathBuf,
     pub output_dir: PathBuf,
     pub youtube_dl_path: String,
     pub ffmpeg_path: String,
+    pub platforms: PlatformRegistry,
 }
 
 pub fn process_music(i: &Music, conf: &EnvConf, global_stat: &mut GlobalStat) {
-    let info = &PLATFORM_INFO[&i.video_type];
-
     let mut output_path = conf.output_dir.clone();
     output_path.push(format!("{}.{}", i.xxhash, "m4a"));
     debug!("Checking destionation: {:?}", output_path);
@@ -63,12 +26,26 @@ pub fn process_music(i: &Music, conf: &EnvConf, global_stat: &mut GlobalStat) {
         return;
     }
 
+    let info = match conf.platforms.get(&i.video_type) {
+        Some(info) => info,
+        None => {
+            let source_path = conf.archive_dir.join(i.source_file(&conf.platforms));
+            debug!("Checking local source: {:?}", source_path);
+            if source_path.exists() {
+                convert(i, &source_path, output_path, conf);
+            } else {
+                warn!("Local source {:?} not found. Skipping.", source_path);
+            }
+            return;
+        }
+    };
+
     let mut source_path = conf.source_dir.clone();
-    source_path.push(format!("{}.{}", i.video_id, info.source_ext));
+    source_path.push(i.source_file(&conf.platforms));
     debug!("Checking source: {:?}", source_path);
 
     if !source_path.exists() {
-        let source_set = (i.video_type, i.video_id.clone());
+        let source_set = (i.video_type.clone(), i.source_file(&conf.platforms));
         if global_stat.failed_video_items.contains(&source_set) {
             info!("{:?} has failed before. Skipping.", source_set);
             return;
@@ -76,10 +53,11 @@ pub fn process_music(i: &Music, conf: &EnvConf, global_stat: &mut GlobalStat) {
         info!("Downloading {}", i);
         let mut cmd = Command::new(&conf.youtube_dl_path);
         cmd.arg("-f")
-            .arg(info.format)
+            .arg(&info.format)
+            .args(&info.downloader_args)
             .arg("-o")
             .arg(&source_path)
-            .arg(info.url_template.replace("{}", &i.video_id));
+            .arg(i.source_url(&conf.platforms).unwrap());
         debug!("Running: {:?}", cmd);
         let output = cmd.output().expect("Failed to execute youtube-dl");
         let status_code = output.status;
@@ -99,33 +77,34 @@ pub fn process_music(i: &Music, conf: &EnvConf, global_stat: &mut GlobalStat) {
             warn!("stderr:\n{}", stderr);
             global_stat
                 .failed_video_items
-                .insert((i.video_type, i.video_id.clone()));
+                .insert((i.video_type.clone(), i.source_file(&conf.platforms)));
             return;
         }
     } else {
         info!("Skipping download: found {:?}", source_path);
     }
 
+    convert(i, &source_path, output_path, conf);
+}
+
+fn convert(i: &Music, source_path: &Path, output_path: PathBuf, conf: &EnvConf) {
     info!("Converting {}", i);
     let mut ffmpeg_cmd = Command::new(&conf.ffmpeg_path);
     ffmpeg_cmd
         .arg("-i")
-        .arg(&source_path)
+        .arg(source_path)
         .arg("-acodec")
         .arg("copy")
         .arg("-movflags")
         .arg("faststart")
-        .arg("-metadata")
-        .arg(format!("title={} / {}", i.title, i.artist))
-        .arg("-metadata")
-        .arg(format!("artist={}", i.performer))
+        .args(tags(i))
         .arg("-vn");
 
-    if i.clip_start.is_some() {
-        ffmpeg_cmd.arg("-ss").arg(i.clip_start.unwrap().to_string());
+    if let Some(clip_start) = i.clip_start {
+        ffmpeg_cmd.arg("-ss").arg(clip_start.to_string());
     }
-    if i.clip_end.is_some() {
-        ffmpeg_cmd.arg("-to").arg(i.clip_end.unwrap().to_string());
+    if let Some(clip_end) = i.clip_end {
+        ffmpeg_cmd.arg("-to").arg(clip_end.to_string());
     }
 
     debug!("Running: {:?}", ffmpeg_cmd);
@@ -147,3 +126,49 @@ pub fn process_music(i: &Music, conf: &EnvConf, global_stat: &mut GlobalStat) {
         warn!("stderr:\n{}", stderr);
     }
 }
+
+fn tags(i: &Music) -> [String; 4] {
+    [
+        "-metadata".to_string(),
+        format!("title={} / {}", i.title, i.artist),
+        "-metadata".to_string(),
+        format!("artist={}", i.performer),
+    ]
+}
+
+/// Move the output built under `old_hash` to the current hash of `i`, updating
+/// its tags. Returns whether it succeeded.
+pub fn retag_music(i: &Music, old_hash: &str, conf: &EnvConf) -> bool {
+    let old_path = conf.output_dir.join(format!("{}.{}", old_hash, "m4a"));
+    let output_path = conf.output_dir.join(format!("{}.{}", i.xxhash, "m4a"));
+    if !old_path.exists() {
+        return false;
+    }
+
+    info!("Retagging {} (was {})", i, old_hash);
+    let mut ffmpeg_cmd = Command::new(&conf.ffmpeg_path);
+    ffmpeg_cmd
+        .arg("-i")
+        .arg(&old_path)
+        .arg("-c")
+        .arg("copy")
+        .arg("-map_metadata")
+        .arg("0")
+        .args(tags(i))
+        .arg(&output_path);
+    debug!("Running: {:?}", ffmpeg_cmd);
+    let output = ffmpeg_cmd.output().expect("Failed to execute ffmpeg");
+    if !output.status.success() {
+        let stderr = std::str::from_utf8(&output.stderr).unwrap_or("[Failed to decode stderr]");
+        warn!(
+            "Retagging failure: non-zero status code: {}.",
+            output.status
+        );
+        warn!("stderr:\n{}", stderr);
+        return false;
+    }
+    if let Err(e) = std::fs::remove_file(&old_path) {
+        warn!("Failed to remove {:?}: {}", old_path, e);
+    }
+    true
+}
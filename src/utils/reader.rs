@@ -0,0 +1,176 @@
+use std::fmt::{self, Display, Formatter};
+use std::io::Read;
+
+use anyhow::{anyhow, Result};
+use csv::{Reader, StringRecord};
+use levenshtein::levenshtein;
+
+use super::maybemusic::{COLUMNS, OPTIONAL_COLUMNS};
+use super::{MaybeMusic, Row};
+
+/// Records of a CSV source, read one at a time.
+///
+/// A malformed record yields an error and reading goes on with the next one,
+/// unless the source itself fails.
+pub struct Rows<R> {
+    reader: Reader<R>,
+    headers: StringRecord,
+    /// Indices of headers other than [`COLUMNS`] and [`OPTIONAL_COLUMNS`].
+    extra: Vec<usize>,
+    record: StringRecord,
+    done: bool,
+}
+
+/// A record which couldn't be read, along with the line it starts on.
+#[derive(Debug)]
+pub struct RowError {
+    pub line: u64,
+    pub error: csv::Error,
+}
+
+impl Display for RowError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.error)
+    }
+}
+
+impl std::error::Error for RowError {}
+
+impl<R: Read> Rows<R> {
+    /// Fails if the headers can't be read or miss a column.
+    pub fn new(source: R) -> Result<Self> {
+        let mut reader = Reader::from_reader(source);
+        let headers = reader.headers()?.clone();
+        check_headers(&headers)?;
+        let extra = headers
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| !COLUMNS.contains(x) && !OPTIONAL_COLUMNS.contains(x))
+            .map(|(idx, _)| idx)
+            .collect();
+        Ok(Self {
+            reader,
+            headers,
+            extra,
+            record: StringRecord::new(),
+            done: false,
+        })
+    }
+
+    pub fn headers(&self) -> &StringRecord {
+        &self.headers
+    }
+
+    fn error(&self, error: csv::Error) -> RowError {
+        let line = error
+            .position()
+            .or_else(|| self.record.position())
+            .map_or(0, |pos| pos.line());
+        RowError { line, error }
+    }
+}
+
+impl<R: Read> Iterator for Rows<R> {
+    type Item = Result<Row, RowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.reader.read_record(&mut self.record) {
+            Ok(true) => {}
+            Ok(false) => {
+                self.done = true;
+                return None;
+            }
+            Err(e) => {
+                self.done = matches!(e.kind(), csv::ErrorKind::Io(_));
+                return Some(Err(self.error(e)));
+            }
+        }
+        let line = self.record.position().map_or(0, |pos| pos.line());
+        Some(
+            self.record
+                .deserialize::<MaybeMusic>(Some(&self.headers))
+                .map(|mut music| {
+                    for &idx in &self.extra {
+                        let value = self.record.get(idx).unwrap_or_default();
+                        music
+                            .extra
+                            .insert(self.headers[idx].to_string(), value.to_string());
+                    }
+                    Row { line, music }
+                })
+                .map_err(|e| self.error(e)),
+        )
+    }
+}
+
+/// Make sure every one of [`COLUMNS`] is present, suggesting a similar
+/// header for missing ones.
+pub fn check_headers(headers: &StringRecord) -> Result<()> {
+    let unknown = headers
+        .iter()
+        .filter(|x| !COLUMNS.contains(x) && !OPTIONAL_COLUMNS.contains(x))
+        .collect::<Vec<_>>();
+    let missing = COLUMNS
+        .iter()
+        .filter(|x| !headers.iter().any(|header| header == **x))
+        .map(|x| match suggest(x, &unknown) {
+            Some(header) => format!("'{}' (did you mean '{}'?)", x, header),
+            None => format!("'{}'", x),
+        })
+        .collect::<Vec<_>>();
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("Missing columns: {}", missing.join(", ")))
+    }
+}
+
+/// The header closest to `column`, ignoring case and separators.
+fn suggest<'a>(column: &str, headers: &[&'a str]) -> Option<&'a str> {
+    let canonical = |x: &str| x.trim().to_lowercase().replace([' ', '-'], "_");
+    headers
+        .iter()
+        .map(|x| (levenshtein(column, &canonical(x)), *x))
+        .filter(|(dist, _)| *dist <= 2)
+        .min_by_key(|(dist, _)| *dist)
+        .map(|(_, x)| x)
+}
+
+#[test]
+fn test_check_headers() {
+    let headers = |x: &str| StringRecord::from(x.split(',').collect::<Vec<_>>());
+    let all = COLUMNS.join(",");
+    assert!(check_headers(&headers(&all)).is_ok());
+    assert!(check_headers(&headers(&format!("{},lyrics", all))).is_ok());
+    assert_eq!(
+        check_headers(&headers(
+            &all.replace("performer", "Perfomer").replace(",comment", "")
+        ))
+        .unwrap_err()
+        .to_string(),
+        "Missing columns: 'performer' (did you mean 'Perfomer'?), 'comment'"
+    );
+}
+
+#[test]
+fn test_rows() {
+    let source =
+        "datetime,video_type,video_id,clip_start,clip_end,status,title,artist,performer,comment
+2021-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBY,,,0,Bluerose,星街すいせい,星街すいせい,
+2021-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBY,,,0,Bluerose
+2021-06-25T22:30+09:00,YOUTUBE,ZfDYRy17CBY,,,x,Bluerose,星街すいせい,星街すいせい,
+2018-03-27T20:54+09:00,TWITTER,978601113791299585,,,0,Starduster,ジミーサムP,星街すいせい,
+";
+    let rows = Rows::new(source.as_bytes()).unwrap().collect::<Vec<_>>();
+    let lines = rows
+        .iter()
+        .map(|x| match x {
+            Ok(row) => Ok(row.line),
+            Err(e) => Err(e.line),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(lines, [Ok(2), Err(3), Err(4), Ok(5)]);
+}
@@ -0,0 +1,82 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Superseded hashes pointing to the hash replacing them.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Redirects(pub BTreeMap<String, String>);
+
+impl Redirects {
+    /// Redirect `old` to `new`, along with every hash redirected to `old`.
+    pub fn add(&mut self, old: &str, new: &str) {
+        // `new` may be a hash restored by reverting a fix
+        self.0.remove(new);
+        for target in self.0.values_mut() {
+            if target == old {
+                *target = new.to_string();
+            }
+        }
+        if old != new {
+            self.0.insert(old.to_string(), new.to_string());
+        }
+    }
+
+    /// An nginx `map` from old to new URL paths, stored in `$suimu_redirect`.
+    pub fn to_nginx_map(&self, url: impl Fn(&str) -> String) -> String {
+        let mut ret = String::from("map $uri $suimu_redirect {\n");
+        for (old, new) in &self.0 {
+            ret += &format!("    {} {};\n", url_path(&url(old)), url_path(&url(new)));
+        }
+        ret += "}\n";
+        ret
+    }
+
+    /// A `_redirects` file, as read by Netlify and Cloudflare Pages.
+    pub fn to_redirects_file(&self, url: impl Fn(&str) -> String) -> String {
+        self.0
+            .iter()
+            .map(|(old, new)| format!("{} {} 301\n", url_path(&url(old)), url_path(&url(new))))
+            .collect()
+    }
+}
+
+/// Path of a URL, or the URL itself if it has no scheme.
+fn url_path(url: &str) -> &str {
+    match url.find("://") {
+        Some(idx) => {
+            let rest = &url[idx + 3..];
+            rest.find('/').map_or("/", |x| &rest[x..])
+        }
+        None => url,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redirects() {
+        let mut redirects = Redirects::default();
+        redirects.add("a", "b");
+        redirects.add("b", "c");
+        redirects.add("x", "y");
+        assert_eq!(redirects.0["a"], "c");
+        assert_eq!(redirects.0["b"], "c");
+        redirects.add("y", "x");
+        assert_eq!(redirects.0.get("x"), None);
+        assert_eq!(redirects.0["y"], "x");
+
+        let url = |hash: &str| format!("https://cdn.example.com/music/{}.m4a", hash);
+        assert_eq!(
+            redirects.to_redirects_file(url),
+            "/music/a.m4a /music/c.m4a 301\n/music/b.m4a /music/c.m4a 301\n/music/y.m4a /music/x.m4a 301\n"
+        );
+        assert!(
+            redirects
+                .to_nginx_map(url)
+                .contains("    /music/a.m4a /music/c.m4a;\n")
+        );
+    }
+}
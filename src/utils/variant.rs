@@ -0,0 +1,71 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+
+lazy_static! {
+    static ref RE: Regex = Regex::new(r"^(.+?) ?[（\(]([^（\(\)）]+)[）\)]$").unwrap();
+}
+
+/// Parses the trailing bracketed suffix of a title, like `(Acoustic ver.)`,
+/// into a variant label.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct VariantParser {
+    /// Known variant labels.
+    pub labels: Vec<String>,
+}
+
+impl Default for VariantParser {
+    fn default() -> Self {
+        Self {
+            labels: [
+                "Acoustic ver.",
+                "Full ver.",
+                "Short ver.",
+                "TV size",
+                "Piano ver.",
+                "Remix",
+                "Live",
+            ]
+            .iter()
+            .map(|x| x.to_string())
+            .collect(),
+        }
+    }
+}
+
+impl VariantParser {
+    /// Split a title into its base title and variant label, if any. The label
+    /// may be unknown.
+    pub fn split<'a>(&self, title: &'a str) -> (&'a str, Option<&'a str>) {
+        match RE.captures(title) {
+            Some(caps) => (
+                caps.get(1).unwrap().as_str(),
+                Some(caps.get(2).unwrap().as_str().trim()),
+            ),
+            None => (title, None),
+        }
+    }
+
+    pub fn is_known(&self, label: &str) -> bool {
+        self.labels.iter().any(|x| x == label)
+    }
+}
+
+#[test]
+fn test_split() {
+    let parser = VariantParser::default();
+    assert_eq!(parser.split("Stellar Stellar"), ("Stellar Stellar", None));
+    assert_eq!(
+        parser.split("Stellar Stellar (Acoustic ver.)"),
+        ("Stellar Stellar", Some("Acoustic ver."))
+    );
+    assert_eq!(
+        parser.split("ソワレ（TV size）"),
+        ("ソワレ", Some("TV size"))
+    );
+    assert_eq!(parser.split("A (B) (Live)"), ("A (B)", Some("Live")));
+    assert_eq!(parser.split("(Live)"), ("(Live)", None));
+    assert!(parser.is_known("Live"));
+    assert!(!parser.is_known("live"));
+}
@@ -0,0 +1,272 @@
+use std::path::{Component, Path};
+
+use chrono::{DateTime, TimeZone, Utc};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::Platform;
+
+const BV_TABLE: &[u8] = b"fZodR9XQDSUm21yCkr6zBqiveYah8bt4xsWpHnJE7jL5VG3guMTKNPAwcF";
+const BV_POSITIONS: [usize; 6] = [11, 10, 3, 8, 4, 6];
+const BV_XOR: u64 = 177451812;
+const BV_ADD: u64 = 8728348608;
+/// Twitter epoch of snowflake IDs, in milliseconds.
+const TWITTER_EPOCH: u64 = 1288834974657;
+/// Status IDs below this predate snowflakes.
+const FIRST_SNOWFLAKE: u64 = 29700859247;
+
+lazy_static! {
+    static ref YOUTUBE_ID: Regex = Regex::new(r"^[A-Za-z0-9_-]{11}$").unwrap();
+    static ref YOUTUBE_URL: Regex =
+        Regex::new(r"(?:[?&]v=|youtu\.be/|/(?:live|shorts|embed)/)([A-Za-z0-9_-]{11})").unwrap();
+    static ref BV_ID: Regex =
+        Regex::new(r"^BV1[1-9A-HJ-NP-Za-km-z]{9}(\?p=[1-9]\d{0,3})?$").unwrap();
+    static ref BV_URL: Regex = Regex::new(r"(BV1[1-9A-HJ-NP-Za-km-z]{9})").unwrap();
+    static ref AV_URL: Regex = Regex::new(r"(?i)\bav(\d+)").unwrap();
+    static ref PAGE: Regex = Regex::new(r"[?&]p=(\d+)").unwrap();
+    static ref TWITTER_ID: Regex = Regex::new(r"^\d{1,20}$").unwrap();
+    static ref TWITTER_URL: Regex = Regex::new(r"/status(?:es)?/(\d{1,20})").unwrap();
+    static ref NICONICO_ID: Regex = Regex::new(r"^(?:sm|nm|so)\d+$").unwrap();
+    static ref NICONICO_URL: Regex =
+        Regex::new(r"(?:nicovideo\.jp/watch/|nico\.ms/)((?:sm|nm|so)\d+)").unwrap();
+    static ref TWITCH_ID: Regex = Regex::new(r"^\d+$").unwrap();
+    static ref TWITCH_URL: Regex = Regex::new(r"twitch\.tv/(?:\w+/)?videos?/(\d+)").unwrap();
+    static ref SOUNDCLOUD_ID: Regex = Regex::new(r"^[\w-]+/[\w-]+$").unwrap();
+    static ref SOUNDCLOUD_URL: Regex = Regex::new(r"soundcloud\.com/([\w-]+/[\w-]+)").unwrap();
+}
+
+/// Convert a Bilibili `av` number to its `BV` id, if it is small enough for
+/// the legacy encoding.
+pub fn av_to_bv(av: u64) -> Option<String> {
+    let x = (av ^ BV_XOR)
+        .checked_add(BV_ADD)
+        .filter(|x| *x < 58u64.pow(BV_POSITIONS.len() as u32))?;
+    let mut ret = *b"BV1  4 1 7  ";
+    for (i, pos) in BV_POSITIONS.iter().enumerate() {
+        ret[*pos] = BV_TABLE[(x / 58u64.pow(i as u32) % 58) as usize];
+    }
+    Some(String::from_utf8(ret.to_vec()).unwrap())
+}
+
+/// Convert a Bilibili `BV` id to its `av` number.
+pub fn bv_to_av(bv: &str) -> Option<u64> {
+    let bv = bv.as_bytes();
+    if bv.len() != 12 {
+        return None;
+    }
+    let mut x = 0;
+    for (i, pos) in BV_POSITIONS.iter().enumerate() {
+        let digit = BV_TABLE.iter().position(|c| *c == bv[*pos])? as u64;
+        x += digit * 58u64.pow(i as u32);
+    }
+    x.checked_sub(BV_ADD).map(|x| x ^ BV_XOR)
+}
+
+/// Creation time encoded in a Twitter snowflake ID.
+pub fn snowflake_time(id: u64) -> Option<DateTime<Utc>> {
+    if id < FIRST_SNOWFLAKE {
+        return None;
+    }
+    let millis = (id >> 22) + TWITTER_EPOCH;
+    Some(Utc.timestamp_millis(millis as i64))
+}
+
+impl Platform {
+    /// Whether `id` has the syntax of a video ID on this platform.
+    pub fn is_valid_id(&self, id: &str) -> bool {
+        match self {
+            Platform::YouTube => YOUTUBE_ID.is_match(id),
+            Platform::Bilibili => BV_ID.is_match(id),
+            Platform::Twitter => TWITTER_ID.is_match(id),
+            Platform::Niconico => NICONICO_ID.is_match(id),
+            Platform::Twitch => TWITCH_ID.is_match(id),
+            Platform::SoundCloud => SOUNDCLOUD_ID.is_match(id),
+            // A relative path staying inside the archive directory
+            Platform::Local => {
+                let path = Path::new(id);
+                path.extension().is_some()
+                    && path.components().all(|x| matches!(x, Component::Normal(_)))
+            }
+            // Validated by their configured pattern only
+            Platform::Other(_) => true,
+        }
+    }
+
+    /// Extract a video ID from a URL or another form of it, like an `av`
+    /// number on Bilibili.
+    pub fn extract_id(&self, s: &str) -> Option<String> {
+        let s = s.trim();
+        if self.is_valid_id(s) {
+            return Some(s.to_string());
+        }
+        match self {
+            Platform::YouTube => YOUTUBE_URL.captures(s).map(|x| x[1].to_string()),
+            Platform::Bilibili => {
+                let id = match AV_URL.captures(s) {
+                    Some(caps) => av_to_bv(caps[1].parse().ok()?)?,
+                    None => BV_URL.captures(s)?[1].to_string(),
+                };
+                let id = match PAGE.captures(s) {
+                    Some(caps) if &caps[1] != "1" => format!("{}?p={}", id, &caps[1]),
+                    _ => id,
+                };
+                // The page may be out of range
+                self.is_valid_id(&id).then_some(id)
+            }
+            Platform::Twitter => TWITTER_URL.captures(s).map(|x| x[1].to_string()),
+            Platform::Niconico => NICONICO_URL.captures(s).map(|x| x[1].to_string()),
+            Platform::Twitch => TWITCH_URL.captures(s).map(|x| x[1].to_string()),
+            Platform::SoundCloud => SOUNDCLOUD_URL.captures(s).map(|x| x[1].to_string()),
+            Platform::Local | Platform::Other(_) => None,
+        }
+    }
+
+    /// Split the part number off a video ID, like `BV17x411w7KC?p=3` on
+    /// Bilibili. The first part is the same as no part, and an invalid part
+    /// number yields `None`.
+    pub fn split_page<'a>(&self, id: &'a str) -> Option<(&'a str, Option<u32>)> {
+        if *self != Platform::Bilibili {
+            return Some((id, None));
+        }
+        match id.split_once("?p=") {
+            Some((id, page)) => match page.parse() {
+                Ok(0) | Err(_) => None,
+                Ok(1) => Some((id, None)),
+                Ok(page) => Some((id, Some(page))),
+            },
+            None => Some((id, None)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bv() {
+        assert_eq!(av_to_bv(170001).as_deref(), Some("BV17x411w7KC"));
+        assert_eq!(av_to_bv(u64::MAX), None);
+        assert_eq!(bv_to_av("BV17x411w7KC"), Some(170001));
+        assert_eq!(bv_to_av("BV1xx"), None);
+    }
+
+    #[test]
+    fn test_snowflake_time() {
+        assert_eq!(
+            snowflake_time(978601113791299585).unwrap().to_rfc3339(),
+            "2018-03-27T11:54:21.714+00:00"
+        );
+        assert_eq!(snowflake_time(20), None);
+    }
+
+    #[test]
+    fn test_extract_id() {
+        assert!(Platform::YouTube.is_valid_id("ZfDYRy17CBY"));
+        assert!(!Platform::YouTube.is_valid_id("ZfDYRy17CB"));
+        assert_eq!(
+            Platform::YouTube
+                .extract_id("https://www.youtube.com/watch?v=ZfDYRy17CBY&t=10s")
+                .as_deref(),
+            Some("ZfDYRy17CBY")
+        );
+        assert_eq!(
+            Platform::YouTube
+                .extract_id("https://youtu.be/ZfDYRy17CBY")
+                .as_deref(),
+            Some("ZfDYRy17CBY")
+        );
+        assert_eq!(
+            Platform::Bilibili.extract_id("av170001").as_deref(),
+            Some("BV17x411w7KC")
+        );
+        assert_eq!(
+            Platform::Bilibili.extract_id("av18446744073709551615"),
+            None
+        );
+        assert_eq!(
+            Platform::Bilibili
+                .extract_id("https://www.bilibili.com/video/BV17x411w7KC/")
+                .as_deref(),
+            Some("BV17x411w7KC")
+        );
+        assert_eq!(
+            Platform::Twitter
+                .extract_id("https://twitter.com/suisei_hosimati/status/978601113791299585")
+                .as_deref(),
+            Some("978601113791299585")
+        );
+        assert_eq!(Platform::Twitter.extract_id("suisei_hosimati"), None);
+        assert_eq!(
+            Platform::Niconico
+                .extract_id("https://www.nicovideo.jp/watch/sm9?ref=top")
+                .as_deref(),
+            Some("sm9")
+        );
+        assert_eq!(
+            Platform::Twitch
+                .extract_id("https://www.twitch.tv/videos/1234567890")
+                .as_deref(),
+            Some("1234567890")
+        );
+        assert_eq!(
+            Platform::SoundCloud
+                .extract_id("https://soundcloud.com/hoshimachi-suisei/bluerose?in=x")
+                .as_deref(),
+            Some("hoshimachi-suisei/bluerose")
+        );
+    }
+
+    #[test]
+    fn test_local() {
+        assert!(Platform::Local.is_valid_id("live/2021-first-live.mkv"));
+        assert!(!Platform::Local.is_valid_id("/live/2021-first-live.mkv"));
+        assert!(!Platform::Local.is_valid_id("../2021-first-live.mkv"));
+        assert!(!Platform::Local.is_valid_id("live"));
+    }
+
+    #[test]
+    fn test_page() {
+        assert!(Platform::Bilibili.is_valid_id("BV17x411w7KC?p=3"));
+        assert!(!Platform::Bilibili.is_valid_id("BV17x411w7KC?p=0"));
+        assert_eq!(
+            Platform::Bilibili
+                .extract_id("https://www.bilibili.com/video/BV17x411w7KC?spm_id_from=333&p=3")
+                .as_deref(),
+            Some("BV17x411w7KC?p=3")
+        );
+        assert!(!Platform::Bilibili.is_valid_id("BV17x411w7KC?p=99999999999"));
+        assert_eq!(
+            Platform::Bilibili
+                .extract_id("https://www.bilibili.com/video/BV17x411w7KC?p=99999999999"),
+            None
+        );
+        assert_eq!(
+            Platform::Bilibili
+                .extract_id("https://www.bilibili.com/video/BV17x411w7KC?p=1")
+                .as_deref(),
+            Some("BV17x411w7KC")
+        );
+        assert_eq!(
+            Platform::Bilibili.split_page("BV17x411w7KC?p=3"),
+            Some(("BV17x411w7KC", Some(3)))
+        );
+        assert_eq!(
+            Platform::Bilibili.split_page("BV17x411w7KC?p=1"),
+            Some(("BV17x411w7KC", None))
+        );
+        assert_eq!(Platform::Bilibili.split_page("BV17x411w7KC?p=0"), None);
+        assert_eq!(
+            Platform::Bilibili.split_page("BV17x411w7KC?p=99999999999"),
+            None
+        );
+        assert_eq!(
+            Platform::Bilibili.split_page("BV17x411w7KC"),
+            Some(("BV17x411w7KC", None))
+        );
+        assert_eq!(
+            Platform::YouTube.split_page("ZfDYRy17CBY"),
+            Some(("ZfDYRy17CBY", None))
+        );
+    }
+}